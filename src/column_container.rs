@@ -0,0 +1,544 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::ops::{Index, IndexMut};
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::container::Container;
+use crate::error::Error;
+use crate::id::{Id, IdInt};
+
+/// A container in which every element has `N` references, one per reference
+/// column, so the elements can be grouped along several axes at once, such
+/// as by owner and by category.
+///
+/// Columns are addressed by their position in `0..N`; named columns can be
+/// expressed as constants holding those positions. The values and their ids
+/// are stored in an inner 'Container', and the 'references' vector holds the
+/// references of every live id at the index of its bare value, with `None`
+/// for free ids, so the references do not have to follow the values when
+/// they are moved around.
+///
+/// The 'columns' array holds one map per column that groups the live ids by
+/// their reference in that column, so the elements of a reference can be
+/// found without scanning the container. The 'group_index' vector maps every
+/// live id to its position inside its group of every column, so an id can be
+/// dropped from its groups in constant time. Both are derived from the
+/// references and are not serialized.
+#[derive(Serialize)]
+#[serde(bound(serialize = "T: Serialize, I: Serialize, [R; N]: Serialize"))]
+pub struct ColumnContainer<T, const N: usize, I = usize, R = usize> {
+    container: Container<T, I>,
+    references: Vec<Option<[R; N]>>,
+    #[serde(skip)]
+    columns: [HashMap<R, Vec<I>>; N],
+    #[serde(skip)]
+    group_index: Vec<[I; N]>,
+}
+
+impl<T: Clone, const N: usize, I: IdInt, R: Clone> Clone for ColumnContainer<T, N, I, R> {
+    fn clone(&self) -> Self {
+        ColumnContainer {
+            container: self.container.clone(),
+            references: self.references.clone(),
+            columns: self.columns.clone(),
+            group_index: self.group_index.clone(),
+        }
+    }
+}
+
+impl<T, const N: usize, I: IdInt, R: Ord + Hash + Clone> Default for ColumnContainer<T, N, I, R> {
+    fn default() -> Self {
+        Self::with_id_type()
+    }
+}
+
+impl<T, const N: usize, R: Ord + Hash + Clone> ColumnContainer<T, N, usize, R> {
+    /// Creates an empty container that stores its ids as `usize` values.
+    pub fn new() -> Self {
+        Self::with_id_type()
+    }
+
+    /// Creates an empty container that stores its ids as `usize` values,
+    /// with space for at least `capacity` elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_id_type(capacity)
+    }
+}
+
+impl<T, const N: usize, I: IdInt, R: Ord + Hash + Clone> ColumnContainer<T, N, I, R> {
+    /// Creates an empty container that stores its ids as values of the id
+    /// type `I`.
+    pub fn with_id_type() -> Self {
+        ColumnContainer {
+            container: Container::with_id_type(),
+            references: Vec::new(),
+            columns: std::array::from_fn(|_| HashMap::new()),
+            group_index: Vec::new(),
+        }
+    }
+
+    /// Creates an empty container that stores its ids as values of the id
+    /// type `I`, with space for at least `capacity` elements.
+    pub fn with_capacity_and_id_type(capacity: usize) -> Self {
+        ColumnContainer {
+            container: Container::with_capacity_and_id_type(capacity),
+            references: Vec::with_capacity(capacity),
+            columns: std::array::from_fn(|_| HashMap::new()),
+            group_index: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Finds the value associated with the given id and returns a reference
+    /// to it. Returns `None` if the id is not found in the container.
+    pub fn get_from_id(&self, id: Id<T, I>) -> Option<&T> {
+        self.container.get_from_id(id)
+    }
+
+    /// Finds the value associated with the given id and returns a mutable
+    /// reference to it. Returns `None` if the id is not found in the
+    /// container.
+    pub fn get_mut_from_id(&mut self, id: Id<T, I>) -> Option<&mut T> {
+        self.container.get_mut_from_id(id)
+    }
+
+    /// Checks whether the given id is associated with a value in the
+    /// container.
+    pub fn contains_id(&self, id: Id<T, I>) -> bool {
+        self.container.contains_id(id)
+    }
+
+    /// Returns the references of the value associated with the given id, one
+    /// per column. Returns `None` if the id is not found in the container.
+    pub fn references_of(&self, id: Id<T, I>) -> Option<&[R; N]> {
+        self.references.get(id.to_usize())?.as_ref()
+    }
+
+    /// Adds a new element with the given references, one per column, to the
+    /// container and returns its id. Returns `Error::CapacityExhausted` if a
+    /// new id is needed but does not fit into the id type of the container.
+    pub fn add(&mut self, data: T, references: [R; N]) -> Result<Id<T, I>, Error<R>> {
+        let id = self
+            .container
+            .add(data)
+            .map_err(|_| Error::CapacityExhausted)?;
+        if self.references.len() <= id.to_usize() {
+            self.references.resize_with(id.to_usize() + 1, || None);
+        }
+        self.index_references(id.value(), &references);
+        self.references[id.to_usize()] = Some(references);
+        Ok(id)
+    }
+
+    /// Removes the value associated with the given id, together with its
+    /// references in every column. Returns `Error::IdNotFound` if the id is
+    /// not found in the container.
+    pub fn remove(&mut self, id: Id<T, I>) -> Result<(), Error<R>> {
+        self.take(id)?;
+        Ok(())
+    }
+
+    /// Removes the value associated with the given id in the same way as
+    /// 'remove', but returns the removed value instead of dropping it.
+    pub fn take(&mut self, id: Id<T, I>) -> Result<T, Error<R>> {
+        let references = self
+            .references
+            .get_mut(id.to_usize())
+            .and_then(Option::take)
+            .ok_or(Error::IdNotFound(id.to_usize()))?;
+        self.unindex_references(id.value(), &references);
+        Ok(self.container.take(id).expect("This should never fail"))
+    }
+
+    /// Returns an iterator over the ids whose reference in the given column
+    /// is the given key, in no particular order. The iterator is empty if
+    /// the key is not found in the column.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the column is not in `0..N`.
+    pub fn ids_by<Q>(&self, column: usize, key: &Q) -> impl Iterator<Item = Id<T, I>>
+    where
+        R: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.columns[column]
+            .get(key)
+            .into_iter()
+            .flatten()
+            .map(|&id| Id::new(id))
+    }
+
+    /// Returns the number of values whose reference in the given column is
+    /// the given key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the column is not in `0..N`.
+    pub fn count_by<Q>(&self, column: usize, key: &Q) -> usize
+    where
+        R: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.columns[column].get(key).map_or(0, Vec::len)
+    }
+
+    /// Removes every value whose reference in the given column is the given
+    /// key. The whole group is taken out of the column at once, so the values
+    /// are only dropped from the groups of the other columns. Returns
+    /// `Ok(())` if at least one value was removed, or
+    /// `Error::ReferenceNotFound` if the key is not found in the column.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the column is not in `0..N`.
+    pub fn remove_by<Q>(&mut self, column: usize, key: &Q) -> Result<(), Error<R>>
+    where
+        R: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = R> + ?Sized,
+    {
+        let ids = self.columns[column]
+            .remove(key)
+            .ok_or_else(|| Error::ReferenceNotFound(key.to_owned()))?;
+        for id in ids {
+            self.take(Id::new(id))?;
+        }
+        Ok(())
+    }
+
+    /// Sorts the elements in the container by their references in the given
+    /// column, keeping the ids of all elements valid. The sort is stable, so
+    /// sorting by one column and then by another groups the elements by the
+    /// second column and, within each group, by the first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the column is not in `0..N`.
+    pub fn sort_by_column(&mut self, column: usize) {
+        assert!(column < N, "Column {column} out of bounds");
+        let references: Vec<&R> = self
+            .iter_with_references()
+            .map(|(_, references, _)| &references[column])
+            .collect();
+        let mut order: Vec<usize> = (0..references.len()).collect();
+        order.sort_by(|&a, &b| references[a].cmp(references[b]));
+        self.container.permute(order);
+    }
+
+    /// Records the given live id under each of the given references in the
+    /// 'columns' maps, and its position inside every group in the
+    /// 'group_index' vector.
+    fn index_references(&mut self, id: I, references: &[R; N]) {
+        if self.group_index.len() <= id.to_usize() {
+            let start = [I::from_usize(0).expect("This should never fail"); N];
+            self.group_index.resize(id.to_usize() + 1, start);
+        }
+        for (column, reference) in references.iter().enumerate() {
+            let ids = self.columns[column].entry(reference.clone()).or_default();
+            self.group_index[id.to_usize()][column] =
+                I::from_usize(ids.len()).expect("This should never fail");
+            ids.push(id);
+        }
+    }
+
+    /// Removes the given id from its group in every column, dropping the
+    /// groups that become empty. The last id of a group takes the place of
+    /// the removed one, so only its entry in 'group_index' has to be
+    /// updated. Groups that have already been taken out of their column are
+    /// skipped.
+    fn unindex_references(&mut self, id: I, references: &[R; N]) {
+        for (column, reference) in references.iter().enumerate() {
+            let Some(ids) = self.columns[column].get_mut(reference) else {
+                continue;
+            };
+            let position = self.group_index[id.to_usize()][column];
+            ids.swap_remove(position.to_usize());
+            if let Some(&moved) = ids.get(position.to_usize()) {
+                self.group_index[moved.to_usize()][column] = position;
+            }
+            if ids.is_empty() {
+                self.columns[column].remove(reference);
+            }
+        }
+    }
+
+    /// Rebuilds the 'columns' maps and the 'group_index' vector from the
+    /// 'references' vector.
+    fn rebuild_columns(&mut self) {
+        for column in self.columns.iter_mut() {
+            column.clear();
+        }
+        self.group_index.clear();
+        let references = std::mem::take(&mut self.references);
+        for (id, references) in references.iter().enumerate() {
+            if let Some(references) = references {
+                let id = I::from_usize(id).expect("This should never fail");
+                self.index_references(id, references);
+            }
+        }
+        self.references = references;
+    }
+
+    /// Checks that the 'references' vector holds references for exactly the
+    /// live ids of the inner container.
+    fn validate(&self) -> Result<(), &'static str> {
+        let live = self
+            .references
+            .iter()
+            .filter(|references| references.is_some());
+        if live.count() != self.container.size()
+            || self
+                .container
+                .ids()
+                .any(|id| self.references_of(id).is_none())
+        {
+            return Err("references do not match the live ids");
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over the values in the container paired with
+    /// their ids and references, in the order the values are stored.
+    pub fn iter_with_references(&self) -> impl Iterator<Item = (Id<T, I>, &[R; N], &T)> {
+        self.container.iter_with_ids().map(|(id, data)| {
+            let references = self.references_of(id).expect("This should never fail");
+            (id, references, data)
+        })
+    }
+
+    /// Returns an iterator over the ids of the values in the container, in
+    /// the order the values are stored.
+    pub fn ids(&self) -> impl Iterator<Item = Id<T, I>> {
+        self.container.ids()
+    }
+
+    /// Returns an iterator over references to the values in the container,
+    /// in the order they are stored.
+    pub fn values(&self) -> std::slice::Iter<'_, T> {
+        self.container.values()
+    }
+
+    /// Returns the number of elements in the container.
+    pub fn size(&self) -> usize {
+        self.container.size()
+    }
+
+    /// Returns true if the container is empty, false otherwise.
+    pub fn empty(&self) -> bool {
+        self.container.empty()
+    }
+
+    /// Clears all elements and references from the container in the same
+    /// way as 'Container::clear'.
+    pub fn clear(&mut self) {
+        self.container.clear();
+        self.references.clear();
+        for column in self.columns.iter_mut() {
+            column.clear();
+        }
+        self.group_index.clear();
+    }
+}
+
+impl<'de, T, const N: usize, I, R> Deserialize<'de> for ColumnContainer<T, N, I, R>
+where
+    T: Deserialize<'de>,
+    I: IdInt,
+    R: Ord + Hash + Clone,
+    [R; N]: Deserialize<'de>,
+{
+    /// Deserializes the inner container, which validates its ids, and the
+    /// references, which must belong to exactly the live ids. The 'columns'
+    /// maps are then rebuilt from the references.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "ColumnContainer")]
+        #[serde(bound(deserialize = "T: Deserialize<'de>, I: IdInt, [R; N]: Deserialize<'de>"))]
+        struct Fields<T, const N: usize, I, R> {
+            container: Container<T, I>,
+            references: Vec<Option<[R; N]>>,
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        let mut container = ColumnContainer {
+            container: fields.container,
+            references: fields.references,
+            columns: std::array::from_fn(|_| HashMap::new()),
+            group_index: Vec::new(),
+        };
+        container.validate().map_err(D::Error::custom)?;
+        container.rebuild_columns();
+        Ok(container)
+    }
+}
+
+impl<T: fmt::Debug, const N: usize, I: IdInt, R: fmt::Debug + Ord + Hash + Clone> fmt::Debug
+    for ColumnContainer<T, N, I, R>
+{
+    /// Formats the container as a map from ids to `(references, value)`
+    /// pairs, in the order the values are stored.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.iter_with_references()
+                    .map(|(id, references, data)| (id.value(), (references, data))),
+            )
+            .finish()
+    }
+}
+
+impl<T, const N: usize, I: IdInt, R: Ord + Hash + Clone> Index<Id<T, I>>
+    for ColumnContainer<T, N, I, R>
+{
+    type Output = T;
+
+    /// Returns a reference to the value associated with the given id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id is not found in the container.
+    fn index(&self, id: Id<T, I>) -> &T {
+        &self.container[id]
+    }
+}
+
+impl<T, const N: usize, I: IdInt, R: Ord + Hash + Clone> IndexMut<Id<T, I>>
+    for ColumnContainer<T, N, I, R>
+{
+    /// Returns a mutable reference to the value associated with the given id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id is not found in the container.
+    fn index_mut(&mut self, id: Id<T, I>) -> &mut T {
+        &mut self.container[id]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWNER: usize = 0;
+    const CATEGORY: usize = 1;
+
+    /// Creates an id for the values of the test containers.
+    fn id(value: usize) -> Id<String> {
+        Id::new(value)
+    }
+
+    /// Creates a container with four values, each with an owner and a
+    /// category column.
+    fn setup_container() -> ColumnContainer<String, 2> {
+        let mut container = ColumnContainer::new();
+        container.add("a".to_string(), [2, 10]).unwrap();
+        container.add("b".to_string(), [1, 20]).unwrap();
+        container.add("c".to_string(), [2, 20]).unwrap();
+        container.add("d".to_string(), [1, 10]).unwrap();
+        container
+    }
+
+    /// Tests the column queries of the ColumnContainer struct to ensure the
+    /// elements can be looked up and removed along each column, and that the
+    /// other columns follow the removals.
+    #[test]
+    fn test_columns() {
+        let mut container = setup_container();
+        let mut owned: Vec<_> = container.ids_by(OWNER, &2).collect();
+        owned.sort();
+        assert_eq!(owned, vec![id(0), id(2)]);
+        assert_eq!(container.count_by(CATEGORY, &20), 2);
+        assert_eq!(container.ids_by(CATEGORY, &30).count(), 0);
+        assert_eq!(container.references_of(id(3)), Some(&[1, 10]));
+
+        assert_eq!(container.remove_by(CATEGORY, &20), Ok(()));
+        assert_eq!(
+            container.remove_by(CATEGORY, &20),
+            Err(Error::ReferenceNotFound(20))
+        );
+        assert_eq!(container.size(), 2);
+        assert_eq!(container.ids_by(OWNER, &2).collect::<Vec<_>>(), vec![id(0)]);
+        assert_eq!(container.ids_by(OWNER, &1).collect::<Vec<_>>(), vec![id(3)]);
+
+        assert_eq!(container.take(id(0)), Ok("a".to_string()));
+        assert_eq!(container.take(id(0)), Err(Error::IdNotFound(0)));
+        assert_eq!(container.count_by(OWNER, &2), 0);
+        assert_eq!(container.count_by(CATEGORY, &10), 1);
+    }
+
+    /// Tests the 'sort_by_column' method of the ColumnContainer struct to
+    /// ensure the elements are ordered by the requested column, that sorting
+    /// is stable across columns, and that ids stay valid.
+    #[test]
+    fn test_sort_by_column() {
+        let mut container = setup_container();
+        container.sort_by_column(OWNER);
+        assert_eq!(
+            container.values().collect::<Vec<_>>(),
+            vec!["b", "d", "a", "c"]
+        );
+
+        container.sort_by_column(CATEGORY);
+        assert_eq!(
+            container.values().collect::<Vec<_>>(),
+            vec!["d", "a", "b", "c"]
+        );
+        assert_eq!(container[id(2)], "c");
+        assert_eq!(container.references_of(id(2)), Some(&[2, 20]));
+    }
+
+    /// Tests the removal of values from large groups of the ColumnContainer
+    /// struct to ensure single removals keep the groups of every column
+    /// consistent, and that a whole group is removed without scanning the
+    /// groups of the other columns for every value.
+    #[test]
+    fn test_remove_large_groups() {
+        let mut container: ColumnContainer<usize, 2> = ColumnContainer::new();
+        for value in 0..100_000 {
+            container.add(value, [value % 2, 0]).unwrap();
+        }
+        for value in (0..100_000).step_by(4) {
+            container.remove(Id::new(value)).unwrap();
+        }
+        assert_eq!(container.count_by(OWNER, &0), 25_000);
+        assert_eq!(container.count_by(CATEGORY, &0), 75_000);
+
+        assert_eq!(container.remove_by(OWNER, &0), Ok(()));
+        assert_eq!(container.count_by(CATEGORY, &0), 50_000);
+        assert!(container.ids_by(CATEGORY, &0).all(|id| id.value() % 2 == 1));
+        assert_eq!(container.remove_by(CATEGORY, &0), Ok(()));
+        assert!(container.empty());
+        assert_eq!(container.count_by(OWNER, &1), 0);
+    }
+
+    /// Tests the serialization of the ColumnContainer struct to ensure the
+    /// references of every column survive a round trip, the column maps are
+    /// rebuilt, and references that do not match the live ids are rejected.
+    #[test]
+    fn test_serde() {
+        let mut container: ColumnContainer<u32, 2, u16, String> = ColumnContainer::with_id_type();
+        container
+            .add(1, ["alice".to_string(), "mesh".to_string()])
+            .unwrap();
+        container
+            .add(2, ["bob".to_string(), "mesh".to_string()])
+            .unwrap();
+        container.remove(Id::new(0)).unwrap();
+
+        let json = serde_json::to_string(&container).unwrap();
+        let restored: ColumnContainer<u32, 2, u16, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.columns, container.columns);
+        assert_eq!(
+            restored.ids_by(1, "mesh").collect::<Vec<_>>(),
+            vec![Id::new(1)]
+        );
+        assert_eq!(restored.count_by(0, "alice"), 0);
+
+        let orphaned = json.replacen("null", r#"["carol","mesh"]"#, 1);
+        assert_ne!(orphaned, json);
+        assert!(serde_json::from_str::<ColumnContainer<u32, 2, u16, String>>(&orphaned).is_err());
+    }
+}
@@ -1,26 +1,84 @@
-#[derive(Default, Debug)]
-pub struct Container<T> {
-    data_index: Vec<usize>,
-    id: Vec<usize>,
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Index, IndexMut};
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::error::Error;
+use crate::handle::Handle;
+use crate::id::{Id, IdInt};
+use crate::removal::Removal;
+
+/// A container that stores its values densely in the 'data' vector and hands
+/// out stable ids to refer to them.
+///
+/// The 'id' vector is split into two partitions: the first `data.len()`
+/// entries are the live ids, in the same order as the values in 'data', and
+/// the remaining entries are the free ids, which are recycled by 'add'. The
+/// 'data_index' vector maps every id, live or free, to its position in the
+/// 'id' vector, and an id is only considered live if that position falls
+/// inside the live partition.
+#[derive(Serialize)]
+pub struct Container<T, I = usize> {
+    data_index: Vec<I>,
+    id: Vec<I>,
     data: Vec<T>,
+    generation: Vec<u32>,
 }
 
-impl<T: Clone> Clone for Container<T> {
+impl<T: Clone, I: IdInt> Clone for Container<T, I> {
     fn clone(&self) -> Self {
         Container {
             data_index: self.data_index.clone(),
             id: self.id.clone(),
             data: self.data.clone(),
+            generation: self.generation.clone(),
         }
     }
 }
 
+impl<T, I: IdInt> Default for Container<T, I> {
+    fn default() -> Self {
+        Self::with_id_type()
+    }
+}
+
 impl<T> Container<T> {
+    /// Creates an empty container that stores its ids as `usize` values.
     pub fn new() -> Self {
+        Self::with_id_type()
+    }
+
+    /// Creates an empty container that stores its ids as `usize` values,
+    /// with space for at least `capacity` elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_id_type(capacity)
+    }
+}
+
+impl<T, I: IdInt> Container<T, I> {
+    /// Creates an empty container that stores its ids as values of the id
+    /// type `I`. Smaller id types shrink the bookkeeping kept for every
+    /// element, but limit the number of ids the container can hand out.
+    pub fn with_id_type() -> Self {
         Container {
             data_index: Vec::new(),
             id: Vec::new(),
             data: Vec::new(),
+            generation: Vec::new(),
+        }
+    }
+
+    /// Creates an empty container that stores its ids as values of the id
+    /// type `I`, with space for at least `capacity` elements.
+    pub fn with_capacity_and_id_type(capacity: usize) -> Self {
+        Container {
+            data_index: Vec::with_capacity(capacity),
+            id: Vec::with_capacity(capacity),
+            data: Vec::with_capacity(capacity),
+            generation: Vec::with_capacity(capacity),
         }
     }
 
@@ -33,74 +91,372 @@ impl<T> Container<T> {
     /// Finds the value associated with the given id and returns a reference
     /// to it. Returns `None` if the id is not found in the container.
     ///
-    /// The method works by first searching for the index of the provided id
-    /// in the 'id' vector, and then using that index to retrieve the
+    /// The method works by first looking up the index of the provided id
+    /// in the 'data_index' vector, and then using that index to retrieve the
     /// corresponding value from the 'data' vector.
-    pub fn get_from_id(&self, id: usize) -> Option<&T> {
-        self.id
-            .iter()
-            .position(|&x| x == id)
-            .and_then(|index| self.data.get(index))
+    pub fn get_from_id(&self, id: Id<T, I>) -> Option<&T> {
+        self.index_of_id(id).and_then(|index| self.data.get(index))
+    }
+
+    /// Retrieves a mutable reference to the value at the given index. Returns
+    /// `Some(&mut T)` if the index is valid, or `None` if the index is out of
+    /// bounds.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.data.get_mut(index)
+    }
+
+    /// Finds the value associated with the given id and returns a mutable
+    /// reference to it. Returns `None` if the id is not found in the
+    /// container.
+    pub fn get_mut_from_id(&mut self, id: Id<T, I>) -> Option<&mut T> {
+        self.index_of_id(id)
+            .and_then(|index| self.data.get_mut(index))
+    }
+
+    /// Calls the given closure with a mutable reference to the value
+    /// associated with the given id and returns the result of the closure.
+    /// Returns `Error::IdNotFound` if the id is not found in the container.
+    pub fn modify<R>(&mut self, id: Id<T, I>, f: impl FnOnce(&mut T) -> R) -> Result<R, Error> {
+        self.get_mut_from_id(id)
+            .map(f)
+            .ok_or(Error::IdNotFound(id.to_usize()))
+    }
+
+    /// Returns the entry for the given id, which is either occupied by a
+    /// value or vacant. The entry allows a value to be inspected, modified
+    /// or inserted with a single id lookup. Only ids that were handed out
+    /// before can be vacant, so `Error::IdNotFound` is returned for ids past
+    /// the end of the 'id' vector.
+    pub fn entry(&mut self, id: Id<T, I>) -> Result<Entry<'_, T, I>, Error> {
+        if id.to_usize() >= self.id.len() {
+            return Err(Error::IdNotFound(id.to_usize()));
+        }
+        Ok(match self.index_of_id(id) {
+            Some(index) => Entry::Occupied(OccupiedEntry {
+                container: self,
+                id,
+                index,
+            }),
+            None => Entry::Vacant(VacantEntry {
+                container: self,
+                id,
+            }),
+        })
+    }
+
+    /// Returns mutable references to the values associated with each of the
+    /// given ids at once, in the same order as the ids. Returns `None` if any
+    /// of the ids is not found in the container, or if the same id is given
+    /// more than once.
+    pub fn get_many_mut<const N: usize>(&mut self, ids: [Id<T, I>; N]) -> Option<[&mut T; N]> {
+        let mut indices = [0; N];
+        for (index, id) in indices.iter_mut().zip(ids) {
+            *index = self.index_of_id(id)?;
+        }
+        self.data.get_disjoint_mut(indices).ok()
+    }
+
+    /// Returns the index in the 'data' vector of the value associated with
+    /// the given id, or `None` if the id is not found in the container. The
+    /// lookup goes through the 'data_index' vector and runs in constant time.
+    pub fn index_of_id(&self, id: Id<T, I>) -> Option<usize> {
+        self.index_of(id.to_usize())
+    }
+
+    /// Returns the index in the 'data' vector of the value associated with
+    /// the given bare id, or `None` if the id is not live.
+    fn index_of(&self, id: usize) -> Option<usize> {
+        self.data_index
+            .get(id)
+            .map(|index| index.to_usize())
+            .filter(|&index| {
+                self.live_ids()
+                    .get(index)
+                    .is_some_and(|live_id| live_id.to_usize() == id)
+            })
+    }
+
+    /// Checks if the given id is associated with a value in the container.
+    /// Returns `true` if the id is found, and `false` otherwise.
+    pub fn contains_id(&self, id: Id<T, I>) -> bool {
+        self.index_of_id(id).is_some()
     }
 
     /// Updates the value associated with the given id to the new data provided.
-    /// Returns `Ok(())` if the update is successful, or an error message if
-    /// the id is not found in the container or if the data index is out of bounds.
-    pub fn update(&mut self, id: usize, new_data: T) -> Result<(), &'static str> {
-        if let Some(index) = self.id.iter().position(|&x| x == id) {
-            if let Some(data_ref) = self.data.get_mut(index) {
-                *data_ref = new_data;
-                Ok(())
-            } else {
-                Err("Data index out of bounds")
-            }
+    /// Returns `Ok(())` if the update is successful, or an error if
+    /// the id is not found in the container.
+    pub fn update(&mut self, id: Id<T, I>, new_data: T) -> Result<(), Error> {
+        if let Some(index) = self.index_of_id(id) {
+            self.data[index] = new_data;
+            Ok(())
         } else {
-            Err("ID not found in the container")
+            Err(Error::IdNotFound(id.to_usize()))
         }
     }
 
     /// Retrieves the id associated with the given index. Returns
-    /// `Ok(usize)' if the index is valid, or an error message if the index
+    /// `Ok(Id<T, I>)' if the index is valid, or an error if the index
     /// is out of bounds.
-    pub fn get_id_from_index(&self, index: usize) -> Result<usize, &'static str> {
-        self.id.get(index).copied().ok_or("Index out of bounds")
+    pub fn get_id_from_index(&self, index: usize) -> Result<Id<T, I>, Error> {
+        self.live_ids()
+            .get(index)
+            .map(|&id| Id::new(id))
+            .ok_or(Error::IndexOutOfBounds(index))
+    }
+
+    /// Returns the live partition of the 'id' vector, i.e. the ids of the
+    /// values currently stored in the container, in the same order as 'data'.
+    fn live_ids(&self) -> &[I] {
+        &self.id[..self.data.len()]
+    }
+
+    /// Checks that the 'id' and 'data_index' vectors describe a consistent
+    /// container: every id maps back to its own position, there are no more
+    /// values than ids, and every id has a generation.
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.id.len() != self.data_index.len() {
+            return Err("id and data_index vectors have different lengths");
+        }
+        if self.data.len() > self.id.len() {
+            return Err("data vector is longer than the id vector");
+        }
+        if self.generation.len() < self.id.len() {
+            return Err("generation vector is shorter than the id vector");
+        }
+        for (index, &id) in self.id.iter().enumerate() {
+            if self
+                .data_index
+                .get(id.to_usize())
+                .map(|index| index.to_usize())
+                != Some(index)
+            {
+                return Err("id and data_index vectors are inconsistent");
+            }
+        }
+        Ok(())
     }
 
     /// Finds the value associated with the given id and swaps it with the
     /// last element in the container, then removes the last element.
-    /// Returns Ok(()) if the id is found and removed successfully, or an
-    /// error message if the id is not found in the container.
-    pub fn remove(&mut self, id: usize) -> Result<(), &'static str> {
-        if let Some(index) = self.id.iter().position(|&x| x == id) {
+    /// Returns Ok(()) if the id is found and removed successfully, or
+    /// `Error::IdNotFound` if the id is not found in the container.
+    pub fn remove(&mut self, id: Id<T, I>) -> Result<(), Error> {
+        self.take(id)?;
+        Ok(())
+    }
+
+    /// Removes the value associated with the given id in the same way as
+    /// 'remove', but returns the removed value instead of dropping it.
+    /// Returns `Error::IdNotFound` if the id is not found in the container.
+    pub fn take(&mut self, id: Id<T, I>) -> Result<T, Error> {
+        self.take_with_relocation(id).map(|(data, _)| data)
+    }
+
+    /// Removes the value associated with the given id in the same way as
+    /// 'remove', and reports which element, if any, was moved into the
+    /// index of the removed one. This allows external mirrors of the 'data'
+    /// vector to be patched without scanning it. Returns
+    /// `Error::IdNotFound` if the id is not found in the container.
+    pub fn remove_with_relocation(&mut self, id: Id<T, I>) -> Result<Removal<T, I>, Error> {
+        self.take_with_relocation(id).map(|(_, removal)| removal)
+    }
+
+    /// Swaps the value associated with the given id with the last element
+    /// in the container and pops it, returning the removed value together
+    /// with a description of the element that took its place.
+    fn take_with_relocation(&mut self, id: Id<T, I>) -> Result<(T, Removal<T, I>), Error> {
+        if let Some(index) = self.index_of_id(id) {
             let last_index = self.data.len() - 1;
 
+            self.generation[id.to_usize()] = self.generation[id.to_usize()].wrapping_add(1);
+
             self.swap(index, last_index)?;
 
-            self.data.pop();
+            let moved = if index != last_index {
+                Some((Id::new(self.id[index]), last_index, index))
+            } else {
+                None
+            };
+            let removal = Removal {
+                removed_index: index,
+                moved,
+            };
 
-            Ok(())
+            Ok((self.data.pop().expect("This should never fail"), removal))
         } else {
-            Err("ID not found in the container")
+            Err(Error::IdNotFound(id.to_usize()))
         }
     }
 
+    /// Replaces the value associated with the given id with the new data
+    /// provided and returns the previous value. Returns `Error::IdNotFound`
+    /// if the id is not found in the container.
+    pub fn replace(&mut self, id: Id<T, I>, new_data: T) -> Result<T, Error> {
+        self.get_mut_from_id(id)
+            .map(|data| std::mem::replace(data, new_data))
+            .ok_or(Error::IdNotFound(id.to_usize()))
+    }
+
     /// Adds a new element to the container and returns a reference to its
     /// associated id. If the container has space (i.e., the length of
     /// 'data' is less than the length of 'id'), it simply pushes the new
     /// data. Otherwise, it pushes the new data and also updates the 'id'
     /// and 'data_index' vectors accordingly. The method ensures that the
     /// new element is properly indexed and can be retrieved using its id in
-    /// the future.
-    pub fn add(&mut self, data: T) -> usize {
+    /// the future. Returns `Error::CapacityExhausted` if a new id is needed
+    /// but does not fit into the id type of the container.
+    pub fn add(&mut self, data: T) -> Result<Id<T, I>, Error> {
+        let index = self.data.len();
+        if self.data.len() >= self.id.len() {
+            let new_id = I::from_usize(index).ok_or(Error::CapacityExhausted)?;
+            self.id.push(new_id);
+            self.data_index.push(new_id);
+            if self.generation.len() < self.id.len() {
+                self.generation.push(0);
+            }
+        }
+        self.data.push(data);
+        Ok(Id::new(self.id[index]))
+    }
+
+    /// Inserts the given data under the given free id and returns its index
+    /// in the 'data' vector. The requested id is swapped to the front of the
+    /// free partition so that pushing the data makes it live.
+    fn insert_at(&mut self, id: usize, data: T) -> usize {
         let index = self.data.len();
-        if self.data.len() < self.id.len() {
-            self.data.push(data);
+        let position = self.data_index[id];
+        let displaced_id = self.id[index];
+        self.id.swap(index, position.to_usize());
+        self.data_index[id] = I::from_usize(index).expect("This should never fail");
+        self.data_index[displaced_id.to_usize()] = position;
+
+        self.data.push(data);
+        index
+    }
+
+    /// Adds a new element to the container in the same way as 'add', but
+    /// returns a generational handle to the element instead of its bare id.
+    pub fn add_with_handle(&mut self, data: T) -> Result<Handle<T, I>, Error> {
+        let id = self.add(data)?;
+        Ok(Handle::new(id, self.generation[id.to_usize()]))
+    }
+
+    /// Adds all elements of the given iterator to the container and returns
+    /// their ids, in the same order as the elements. The bookkeeping for all
+    /// elements is done at once: free ids are recycled first, and the new ids
+    /// needed for the rest are appended in a single pass. Returns
+    /// `Error::CapacityExhausted`, without adding any element, if the id type
+    /// of the container cannot hold the ids needed for all of them.
+    pub fn add_many(&mut self, data: impl IntoIterator<Item = T>) -> Result<Vec<Id<T, I>>, Error> {
+        let data: Vec<T> = data.into_iter().collect();
+        let start = self.reserve_ids(data.len())?;
+        self.data.extend(data);
+        Ok(self.id[start..self.data.len()]
+            .iter()
+            .map(|&id| Id::new(id))
+            .collect())
+    }
+
+    /// Makes sure there are at least `count` free ids after the live ones,
+    /// appending new ids to the 'id' and 'data_index' vectors as needed, and
+    /// returns the index at which the new elements will be stored. Returns
+    /// `Error::CapacityExhausted` if the new ids do not fit into the id type.
+    fn reserve_ids(&mut self, count: usize) -> Result<usize, Error> {
+        let start = self.data.len();
+        let end = start + count;
+        if end > self.id.len() && I::from_usize(end - 1).is_none() {
+            return Err(Error::CapacityExhausted);
+        }
+        self.reserve(count);
+        for new_id in self.id.len()..end {
+            let new_id = I::from_usize(new_id).expect("This should never fail");
+            self.id.push(new_id);
+            self.data_index.push(new_id);
+        }
+        if self.generation.len() < self.id.len() {
+            self.generation.resize(self.id.len(), 0);
+        }
+        Ok(start)
+    }
+
+    /// Removes the values associated with all of the given ids in the same
+    /// way as 'remove'. Every id is attempted, and the errors for all ids that
+    /// could not be removed are returned together instead of stopping at the
+    /// first one.
+    pub fn remove_many(&mut self, ids: &[Id<T, I>]) -> Result<(), Vec<Error>> {
+        let errors: Vec<Error> = ids.iter().filter_map(|&id| self.remove(id).err()).collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Keeps only the elements for which the given predicate returns `true`,
+    /// removing all others in a single pass. The predicate receives the id of
+    /// every element and a mutable reference to its value. Removed elements
+    /// are swap-removed in the same way as 'remove'.
+    pub fn retain(&mut self, mut f: impl FnMut(Id<T, I>, &mut T) -> bool) {
+        self.drain_filter(|id, data| !f(id, data)).for_each(drop);
+    }
+
+    /// Returns an iterator that removes the elements for which the given
+    /// predicate returns `true` and yields them paired with their ids.
+    /// Elements are only removed while the iterator is advanced, so dropping
+    /// it early leaves the remaining elements in the container.
+    pub fn drain_filter<F>(&mut self, f: F) -> DrainFilter<'_, T, I, F>
+    where
+        F: FnMut(Id<T, I>, &mut T) -> bool,
+    {
+        DrainFilter {
+            container: self,
+            index: 0,
+            predicate: f,
+        }
+    }
+
+    /// Returns a generational handle to the element associated with the given
+    /// id, or `None` if the id is not found in the container.
+    pub fn handle_of(&self, id: Id<T, I>) -> Option<Handle<T, I>> {
+        if self.contains_id(id) {
+            Some(Handle::new(id, self.generation[id.to_usize()]))
         } else {
-            self.data.push(data);
-            self.id.push(index);
-            self.data_index.push(index);
+            None
+        }
+    }
+
+    /// Finds the value the given handle points to and returns a reference to
+    /// it. Returns `None` if the id of the handle is not found in the
+    /// container or if the handle is stale.
+    pub fn get_from_handle(&self, handle: Handle<T, I>) -> Option<&T> {
+        self.id_from_handle(handle)
+            .ok()
+            .and_then(|id| self.get_from_id(id))
+    }
+
+    /// Updates the value the given handle points to. Behaves like 'update',
+    /// but returns `Error::StaleHandle` if the handle is stale.
+    pub fn update_by_handle(&mut self, handle: Handle<T, I>, new_data: T) -> Result<(), Error> {
+        let id = self.id_from_handle(handle)?;
+        self.update(id, new_data)
+    }
+
+    /// Removes the value the given handle points to. Behaves like 'remove',
+    /// but returns `Error::StaleHandle` if the handle is stale.
+    pub fn remove_by_handle(&mut self, handle: Handle<T, I>) -> Result<(), Error> {
+        let id = self.id_from_handle(handle)?;
+        self.remove(id)
+    }
+
+    /// Resolves the given handle to its id. Returns an error if the
+    /// id of the handle was never handed out by the container, or if the
+    /// generation of the handle no longer matches the generation of the id.
+    fn id_from_handle(&self, handle: Handle<T, I>) -> Result<Id<T, I>, Error> {
+        match self.generation.get(handle.id().to_usize()) {
+            Some(&generation) if generation == handle.generation() => Ok(handle.id()),
+            Some(_) => Err(Error::StaleHandle),
+            None => Err(Error::IdNotFound(handle.id().to_usize())),
         }
-        self.id.get(index).copied().expect("This should never fail")
     }
 
     /// Swaps the elements at the specified indices in the container. This
@@ -108,18 +464,102 @@ impl<T> Container<T> {
     /// corresponding elements in the 'data', 'id', and
     /// 'reference' vectors are swapped together. It also updates the
     /// 'data_index' vector to reflect the new positions of the swapped elements.
-    fn swap(&mut self, index_a: usize, index_b: usize) -> Result<(), &'static str> {
+    fn swap(&mut self, index_a: usize, index_b: usize) -> Result<(), Error> {
         self.data.swap(index_a, index_b);
         self.id.swap(index_a, index_b);
 
-        let data_index_a = self.get_id_from_index(index_a)?;
-        let data_index_b = self.get_id_from_index(index_b)?;
+        let data_index_a = self.get_id_from_index(index_a)?.to_usize();
+        let data_index_b = self.get_id_from_index(index_b)?.to_usize();
 
         self.data_index.swap(data_index_a, data_index_b);
 
         Ok(())
     }
 
+    /// Sorts the elements in the container with the given comparator, keeping
+    /// the ids of all elements valid. The sort is stable, and values are moved
+    /// into place without being cloned. If the comparator panics, the
+    /// container is left unchanged.
+    pub fn sort_by(&mut self, mut compare: impl FnMut(&T, &T) -> Ordering) {
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        order.sort_by(|&a, &b| compare(&self.data[a], &self.data[b]));
+        self.permute(order);
+    }
+
+    /// Sorts the elements in the container by the key extracted with the
+    /// given function, keeping the ids of all elements valid. The sort is
+    /// stable, and values are moved into place without being cloned.
+    pub fn sort_by_key<K: Ord>(&mut self, mut f: impl FnMut(&T) -> K) {
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        order.sort_by_key(|&index| f(&self.data[index]));
+        self.permute(order);
+    }
+
+    /// Sorts the elements in the container with the given comparator, keeping
+    /// the ids of all elements valid. Like 'sort_by', but the order of equal
+    /// elements is not preserved.
+    pub fn sort_unstable_by(&mut self, mut compare: impl FnMut(&T, &T) -> Ordering) {
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        order.sort_unstable_by(|&a, &b| compare(&self.data[a], &self.data[b]));
+        self.permute(order);
+    }
+
+    /// Rearranges the live elements so that the element at index `order[i]`
+    /// ends up at index 'i', then rebuilds 'data_index' for the live ids.
+    /// The permutation is applied in place by following its cycles, so every
+    /// element is moved at most once and never cloned. Containers built on
+    /// top of this one use it to apply orderings that depend on their own
+    /// per-id data.
+    pub(crate) fn permute(&mut self, mut order: Vec<usize>) {
+        for start in 0..order.len() {
+            let mut current = start;
+            loop {
+                let next = order[current];
+                order[current] = current;
+                if next == start {
+                    break;
+                }
+                self.data.swap(current, next);
+                self.id.swap(current, next);
+                current = next;
+            }
+        }
+
+        for (index, id) in self.id[..self.data.len()].iter().enumerate() {
+            self.data_index[id.to_usize()] = I::from_usize(index).expect("This should never fail");
+        }
+    }
+
+    /// Returns an iterator over references to the values in the container,
+    /// in the order they are stored in the 'data' vector.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    /// Returns an iterator over mutable references to the values in the
+    /// container, in the order they are stored in the 'data' vector.
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.data.iter_mut()
+    }
+
+    /// Returns an iterator over the ids of the values in the container, in
+    /// the same order as the values are stored in the 'data' vector.
+    pub fn ids(&self) -> impl Iterator<Item = Id<T, I>> {
+        self.live_ids().iter().map(|&id| Id::new(id))
+    }
+
+    /// Returns an iterator over references to the values in the container.
+    /// This is the same as 'iter', provided to mirror 'ids'.
+    pub fn values(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    /// Returns an iterator over the values in the container paired with
+    /// their ids, yielding `(id, &T)` in the order of the 'data' vector.
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (Id<T, I>, &T)> {
+        self.ids().zip(self.data.iter())
+    }
+
     /// Returns the number of elements currently stored in the container by
     /// returning the length of the 'data' vector.
     pub fn size(&self) -> usize {
@@ -133,27 +573,383 @@ impl<T> Container<T> {
         self.data.is_empty()
     }
 
+    /// Returns the number of elements the container can hold without
+    /// reallocating the 'data' vector.
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    /// Reserves space for at least `additional` more elements. Free ids are
+    /// taken into account, so the id bookkeeping only grows by the number of
+    /// ids that cannot be recycled.
+    pub fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+        let new_ids = (self.data.len() + additional).saturating_sub(self.id.len());
+        self.id.reserve(new_ids);
+        self.data_index.reserve(new_ids);
+        self.generation
+            .reserve((self.id.len() + new_ids).saturating_sub(self.generation.len()));
+    }
+
+    /// Shrinks the capacity of the internal vectors as much as possible. This
+    /// does not release free ids; use 'compact_ids' for that.
+    pub fn shrink_to_fit(&mut self) {
+        self.data.shrink_to_fit();
+        self.id.shrink_to_fit();
+        self.data_index.shrink_to_fit();
+        self.generation.shrink_to_fit();
+    }
+
+    /// Renumbers the ids of the container so that the live ids become
+    /// `0..size()`, in the order of the 'data' vector, and releases all free
+    /// ids. Returns a remap table indexed by the bare value of every old id,
+    /// holding the new id of each live element and `None` for ids that were
+    /// free. The generations of all ids are increased, so handles created
+    /// before the compaction are rejected as stale.
+    pub fn compact_ids(&mut self) -> Vec<Option<Id<T, I>>> {
+        let mut remap = vec![None; self.id.len()];
+        for index in 0..self.data.len() {
+            let new_id = I::from_usize(index).expect("This should never fail");
+            remap[self.id[index].to_usize()] = Some(Id::new(new_id));
+            self.id[index] = new_id;
+            self.data_index[index] = new_id;
+        }
+        self.id.truncate(self.data.len());
+        self.data_index.truncate(self.data.len());
+        for generation in self.generation.iter_mut() {
+            *generation = generation.wrapping_add(1);
+        }
+        remap
+    }
+
     /// Clears all elements from the container by clearing the 'data', 'id', and
     /// 'data_index' vectors. This effectively resets the container to an
     /// empty state, allowing it to be reused without any remaining data
-    /// from previous operations.
+    /// from previous operations. The generations of the ids are kept and
+    /// increased, so handles created before the clear are rejected as stale.
     pub fn clear(&mut self) {
+        for generation in self.generation.iter_mut() {
+            *generation = generation.wrapping_add(1);
+        }
         self.data.clear();
         self.id.clear();
         self.data_index.clear();
     }
 }
 
+impl<'de, T: Deserialize<'de>, I: IdInt> Deserialize<'de> for Container<T, I> {
+    /// Deserializes the full state of the container, including the free ids
+    /// and their generations, so ids and handles stay valid across a round
+    /// trip. The container is validated before it is returned, and
+    /// inconsistent 'id' and 'data_index' vectors are rejected.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "Container")]
+        struct Fields<T, I> {
+            data_index: Vec<I>,
+            id: Vec<I>,
+            data: Vec<T>,
+            generation: Vec<u32>,
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        let container = Container {
+            data_index: fields.data_index,
+            id: fields.id,
+            data: fields.data,
+            generation: fields.generation,
+        };
+        container.validate().map_err(D::Error::custom)?;
+        Ok(container)
+    }
+}
+
+impl<T: fmt::Debug, I: IdInt> fmt::Debug for Container<T, I> {
+    /// Formats the container as a map from ids to values, in the order the
+    /// values are stored in the 'data' vector.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.iter_with_ids().map(|(id, data)| (id.value(), data)))
+            .finish()
+    }
+}
+
+impl<T: PartialEq, I: IdInt> PartialEq for Container<T, I> {
+    /// Compares two containers by their id to value mappings. Two containers
+    /// are equal if they associate the same ids with equal values, regardless
+    /// of the order of the values in the 'data' vector or of their free ids.
+    fn eq(&self, other: &Self) -> bool {
+        self.size() == other.size()
+            && self
+                .iter_with_ids()
+                .all(|(id, data)| other.get_from_id(id) == Some(data))
+    }
+}
+
+impl<T: Eq, I: IdInt> Eq for Container<T, I> {}
+
+impl<T: Hash, I: IdInt> Hash for Container<T, I> {
+    /// Hashes the id to value mappings of the container in id order, so
+    /// that containers which compare equal also hash equally.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.size().hash(state);
+        for id in 0..self.data_index.len() {
+            if let Some(index) = self.index_of(id) {
+                id.hash(state);
+                self.data[index].hash(state);
+            }
+        }
+    }
+}
+
+impl<T, I: IdInt> Index<Id<T, I>> for Container<T, I> {
+    type Output = T;
+
+    /// Returns a reference to the value associated with the given id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id is not found in the container.
+    fn index(&self, id: Id<T, I>) -> &T {
+        self.get_from_id(id).expect("ID not found in the container")
+    }
+}
+
+impl<T, I: IdInt> IndexMut<Id<T, I>> for Container<T, I> {
+    /// Returns a mutable reference to the value associated with the given id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id is not found in the container.
+    fn index_mut(&mut self, id: Id<T, I>) -> &mut T {
+        self.get_mut_from_id(id)
+            .expect("ID not found in the container")
+    }
+}
+
+impl<T, I: IdInt> Index<Handle<T, I>> for Container<T, I> {
+    type Output = T;
+
+    /// Returns a reference to the value the given handle points to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle is stale or its id is not found in the container.
+    fn index(&self, handle: Handle<T, I>) -> &T {
+        self.get_from_handle(handle)
+            .expect("Handle is stale or not found in the container")
+    }
+}
+
+impl<T, I: IdInt> IndexMut<Handle<T, I>> for Container<T, I> {
+    /// Returns a mutable reference to the value the given handle points to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle is stale or its id is not found in the container.
+    fn index_mut(&mut self, handle: Handle<T, I>) -> &mut T {
+        let id = self
+            .id_from_handle(handle)
+            .expect("Handle is stale or not found in the container");
+        self.get_mut_from_id(id)
+            .expect("Handle is stale or not found in the container")
+    }
+}
+
+impl<T, I: IdInt> FromIterator<T> for Container<T, I> {
+    fn from_iter<Iter: IntoIterator<Item = T>>(iter: Iter) -> Self {
+        let mut container = Container::with_id_type();
+        container.extend(iter);
+        container
+    }
+}
+
+impl<T, I: IdInt> Extend<T> for Container<T, I> {
+    fn extend<Iter: IntoIterator<Item = T>>(&mut self, iter: Iter) {
+        self.add_many(iter).expect("Container capacity exhausted");
+    }
+}
+
+impl<T, I> IntoIterator for Container<T, I> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    /// Consumes the container and returns an iterator over its values, in
+    /// the order they are stored in the 'data' vector.
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, T, I: IdInt> IntoIterator for &'a Container<T, I> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, I: IdInt> IntoIterator for &'a mut Container<T, I> {
+    type Item = &'a mut T;
+    type IntoIter = std::slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// An iterator that removes the elements matching a predicate from a
+/// container and yields them with their ids. Returned by
+/// 'Container::drain_filter'.
+pub struct DrainFilter<'a, T, I, F> {
+    container: &'a mut Container<T, I>,
+    index: usize,
+    predicate: F,
+}
+
+impl<T, I: IdInt, F> Iterator for DrainFilter<'_, T, I, F>
+where
+    F: FnMut(Id<T, I>, &mut T) -> bool,
+{
+    type Item = (Id<T, I>, T);
+
+    /// Advances through the 'data' vector until an element matches the
+    /// predicate and removes it. Since removal swaps the last element into
+    /// the current index, the index is only advanced past kept elements.
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.container.data.len() {
+            let id = Id::new(self.container.id[self.index]);
+            if (self.predicate)(id, &mut self.container.data[self.index]) {
+                let data = self.container.take(id).expect("This should never fail");
+                return Some((id, data));
+            }
+            self.index += 1;
+        }
+        None
+    }
+}
+
+/// A view into a single id of a container, which is either occupied by a
+/// value or vacant. Returned by 'Container::entry'.
+pub enum Entry<'a, T, I = usize> {
+    Occupied(OccupiedEntry<'a, T, I>),
+    Vacant(VacantEntry<'a, T, I>),
+}
+
+/// An entry for an id that is associated with a value in the container.
+pub struct OccupiedEntry<'a, T, I = usize> {
+    container: &'a mut Container<T, I>,
+    id: Id<T, I>,
+    index: usize,
+}
+
+/// An entry for an id that is not associated with a value in the container.
+pub struct VacantEntry<'a, T, I = usize> {
+    container: &'a mut Container<T, I>,
+    id: Id<T, I>,
+}
+
+impl<'a, T, I: IdInt> Entry<'a, T, I> {
+    /// Returns the id of the entry.
+    pub fn id(&self) -> Id<T, I> {
+        match self {
+            Entry::Occupied(entry) => entry.id(),
+            Entry::Vacant(entry) => entry.id(),
+        }
+    }
+
+    /// Returns a mutable reference to the value of the entry, inserting the
+    /// given default first if the entry is vacant.
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns a mutable reference to the value of the entry, inserting the
+    /// result of the given closure first if the entry is vacant.
+    pub fn or_insert_with(self, f: impl FnOnce() -> T) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// Calls the given closure with a mutable reference to the value if the
+    /// entry is occupied, and returns the entry for further chaining.
+    pub fn and_modify(mut self, f: impl FnOnce(&mut T)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, T, I: IdInt> OccupiedEntry<'a, T, I> {
+    /// Returns the id of the entry.
+    pub fn id(&self) -> Id<T, I> {
+        self.id
+    }
+
+    /// Returns a reference to the value of the entry.
+    pub fn get(&self) -> &T {
+        &self.container.data[self.index]
+    }
+
+    /// Returns a mutable reference to the value of the entry.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.container.data[self.index]
+    }
+
+    /// Converts the entry into a mutable reference to its value, bound to
+    /// the lifetime of the container.
+    pub fn into_mut(self) -> &'a mut T {
+        &mut self.container.data[self.index]
+    }
+
+    /// Replaces the value of the entry and returns the previous value.
+    pub fn insert(&mut self, data: T) -> T {
+        std::mem::replace(self.get_mut(), data)
+    }
+
+    /// Removes the value of the entry from the container in the same way as
+    /// 'Container::take' and returns it.
+    pub fn remove(self) -> T {
+        self.container
+            .take(self.id)
+            .expect("This should never fail")
+    }
+}
+
+impl<'a, T, I: IdInt> VacantEntry<'a, T, I> {
+    /// Returns the id of the entry.
+    pub fn id(&self) -> Id<T, I> {
+        self.id
+    }
+
+    /// Inserts the given value under the id of the entry and returns a
+    /// mutable reference to it.
+    pub fn insert(self, data: T) -> &'a mut T {
+        let index = self.container.insert_at(self.id.to_usize(), data);
+        &mut self.container.data[index]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Helper function to create a typed id for the containers under test.
+    fn id(value: usize) -> Id<String> {
+        Id::new(value)
+    }
+
     /// Helper function to create a sample container for testing purposes.
     fn setup_container() -> Container<String> {
         Container {
             data_index: vec![0, 1, 2],
             id: vec![0, 1, 2],
             data: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            generation: vec![0, 0, 0],
         }
     }
 
@@ -173,6 +969,7 @@ mod tests {
         assert_eq!(container.data, cloned_container.data);
         assert_eq!(container.id, cloned_container.id);
         assert_eq!(container.data_index, cloned_container.data_index);
+        assert_eq!(container.generation, cloned_container.generation);
     }
 
     /// Tests the 'get' method of the Container struct to ensure it
@@ -181,29 +978,43 @@ mod tests {
     #[test]
     fn test_get() {
         let container = setup_container();
-        assert_eq!(container.get_from_id(0), Some(&"a".to_string()));
-        assert_eq!(container.get_from_id(1), Some(&"b".to_string()));
-        assert_eq!(container.get_from_id(2), Some(&"c".to_string()));
-        assert_eq!(container.get_from_id(3), None);
+        assert_eq!(container.get_from_id(id(0)), Some(&"a".to_string()));
+        assert_eq!(container.get_from_id(id(1)), Some(&"b".to_string()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"c".to_string()));
+        assert_eq!(container.get_from_id(id(3)), None);
+    }
+
+    /// Tests the 'index_of_id' and 'contains_id' methods of the Container
+    /// struct to ensure ids are resolved to their current index and that
+    /// removed or unknown ids are reported as missing.
+    #[test]
+    fn test_id_lookup() {
+        let mut container = setup_container();
+        assert_eq!(container.index_of_id(id(1)), Some(1));
+        assert!(container.contains_id(id(2)));
+        container.remove(id(0)).unwrap();
+        assert_eq!(container.index_of_id(id(2)), Some(0));
+        assert_eq!(container.index_of_id(id(0)), None);
+        assert!(!container.contains_id(id(0)));
+        assert!(!container.contains_id(id(3)));
     }
 
     /// Tests the 'update' method of the Container struct to ensure it
     /// correctly updates values based on their associated ids and returns
-    /// appropriate error messages when the id is not found or when the data
-    /// index is out of bounds.
+    /// appropriate errors when the id is not found or has been removed.
     #[test]
     fn test_update() {
         let mut container = setup_container();
-        assert_eq!(container.update(1, "updated".to_string()), Ok(()));
-        assert_eq!(container.get_from_id(1), Some(&"updated".to_string()));
+        assert_eq!(container.update(id(1), "updated".to_string()), Ok(()));
+        assert_eq!(container.get_from_id(id(1)), Some(&"updated".to_string()));
         assert_eq!(
-            container.update(3, "new".to_string()),
-            Err("ID not found in the container")
+            container.update(id(3), "new".to_string()),
+            Err(Error::IdNotFound(3))
         );
-        container.remove(2).unwrap();
+        container.remove(id(2)).unwrap();
         assert_eq!(
-            container.update(2, "new".to_string()),
-            Err("Data index out of bounds")
+            container.update(id(2), "new".to_string()),
+            Err(Error::IdNotFound(2))
         );
     }
 
@@ -213,9 +1024,9 @@ mod tests {
     #[test]
     fn test_remove() {
         let mut container = setup_container();
-        assert_eq!(container.remove(2), Ok(()));
-        assert_eq!(container.get_from_id(2), None);
-        assert_eq!(container.remove(3), Err("ID not found in the container"));
+        assert_eq!(container.remove(id(2)), Ok(()));
+        assert_eq!(container.get_from_id(id(2)), None);
+        assert_eq!(container.remove(id(3)), Err(Error::IdNotFound(3)));
     }
 
     /// Tests the 'add' method of the Container struct to ensure it
@@ -225,10 +1036,10 @@ mod tests {
     #[test]
     fn test_add() {
         let mut container = setup_container();
-        let new_id = container.add("d".to_string());
+        let new_id = container.add("d".to_string()).unwrap();
         assert_eq!(container.get_from_id(new_id), Some(&"d".to_string()));
-        container.remove(1).unwrap();
-        let new_id2 = container.add("e".to_string());
+        container.remove(id(1)).unwrap();
+        let new_id2 = container.add("e".to_string()).unwrap();
         assert_eq!(container.get_from_id(new_id2), Some(&"e".to_string()));
     }
 
@@ -243,4 +1054,484 @@ mod tests {
         assert_eq!(container.size(), 0);
         assert!(container.empty());
     }
+
+    /// Tests the handle related methods of the Container struct to ensure
+    /// handles resolve to their element while it is alive, and that handles
+    /// kept from before a removal are rejected once the id has been reused.
+    #[test]
+    fn test_handles() {
+        let mut container = setup_container();
+        let handle = container.add_with_handle("d".to_string()).unwrap();
+        assert_eq!(container.get_from_handle(handle), Some(&"d".to_string()));
+        assert_eq!(container.handle_of(handle.id()), Some(handle));
+        assert_eq!(container.remove_by_handle(handle), Ok(()));
+        assert_eq!(container.handle_of(handle.id()), None);
+
+        let reused = container.add("e".to_string()).unwrap();
+        assert_eq!(reused, handle.id());
+        assert_eq!(container.get_from_handle(handle), None);
+        assert_eq!(
+            container.update_by_handle(handle, "f".to_string()),
+            Err(Error::StaleHandle)
+        );
+        assert_eq!(container.remove_by_handle(handle), Err(Error::StaleHandle));
+        assert_eq!(container.get_from_id(reused), Some(&"e".to_string()));
+
+        let current = container.handle_of(reused).unwrap();
+        assert_eq!(current.generation(), handle.generation() + 1);
+        container.clear();
+        container.add("d".to_string()).unwrap();
+        assert_eq!(
+            container.get_from_handle(container.handle_of(id(0)).unwrap()),
+            Some(&"d".to_string())
+        );
+        assert_eq!(container.get_from_handle(Handle::new(id(0), 0)), None);
+    }
+
+    /// Tests that ids which have been removed are reliably rejected by every
+    /// operation, even after they have been recycled by a later 'add', and
+    /// that removing an id twice never touches the remaining live elements.
+    #[test]
+    fn test_freed_ids() {
+        let mut container = setup_container();
+        assert_eq!(container.remove(id(0)), Ok(()));
+        assert_eq!(container.remove(id(0)), Err(Error::IdNotFound(0)));
+        assert_eq!(container.size(), 2);
+        assert_eq!(container.get_from_id(id(1)), Some(&"b".to_string()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"c".to_string()));
+        assert_eq!(
+            container.get_id_from_index(2),
+            Err(Error::IndexOutOfBounds(2))
+        );
+
+        assert_eq!(container.remove(id(1)), Ok(()));
+        assert_eq!(container.remove(id(0)), Err(Error::IdNotFound(0)));
+        assert_eq!(container.remove(id(1)), Err(Error::IdNotFound(1)));
+        assert_eq!(container.get_from_id(id(0)), None);
+        assert_eq!(container.get_from_id(id(2)), Some(&"c".to_string()));
+
+        let readded = container.add("d".to_string()).unwrap();
+        assert_eq!(readded, id(1));
+        assert_eq!(container.remove(id(0)), Err(Error::IdNotFound(0)));
+        assert_eq!(container.remove(readded), Ok(()));
+        assert_eq!(
+            container.remove(readded),
+            Err(Error::IdNotFound(readded.value()))
+        );
+        assert_eq!(
+            container.update(readded, "e".to_string()),
+            Err(Error::IdNotFound(readded.value()))
+        );
+        assert_eq!(container.size(), 1);
+        assert_eq!(container.get_from_id(id(2)), Some(&"c".to_string()));
+    }
+
+    /// Tests the iterator methods and 'IntoIterator' implementations of the
+    /// Container struct to ensure they walk the values in dense order and
+    /// pair them with the correct ids, skipping removed elements.
+    #[test]
+    fn test_iterators() {
+        let mut container = setup_container();
+        container.remove(id(0)).unwrap();
+        container.add("d".to_string()).unwrap();
+
+        for value in container.iter_mut() {
+            value.push('!');
+        }
+        assert_eq!(
+            container.ids().collect::<Vec<_>>(),
+            vec![id(2), id(1), id(0)]
+        );
+        assert_eq!(
+            container.values().cloned().collect::<Vec<_>>(),
+            vec!["c!", "b!", "d!"]
+        );
+        assert_eq!(
+            container.iter_with_ids().collect::<Vec<_>>(),
+            vec![
+                (id(2), &"c!".to_string()),
+                (id(1), &"b!".to_string()),
+                (id(0), &"d!".to_string())
+            ]
+        );
+
+        for value in &mut container {
+            value.pop();
+        }
+        assert_eq!((&container).into_iter().count(), 3);
+        assert_eq!(container.iter().next(), Some(&"c".to_string()));
+        assert_eq!(
+            container.into_iter().collect::<Vec<_>>(),
+            vec!["c", "b", "d"]
+        );
+    }
+
+    /// Tests the mutable access methods of the Container struct to ensure
+    /// values can be changed in place by index and by id, and that
+    /// 'get_many_mut' rejects missing and duplicated ids.
+    #[test]
+    fn test_mutable_access() {
+        let mut container = setup_container();
+        container.get_mut(0).unwrap().push('!');
+        assert_eq!(container.get_from_id(id(0)), Some(&"a!".to_string()));
+        assert_eq!(container.get_mut(3), None);
+
+        container.get_mut_from_id(id(1)).unwrap().push('?');
+        assert_eq!(container.get_from_id(id(1)), Some(&"b?".to_string()));
+        assert_eq!(container.get_mut_from_id(id(3)), None);
+
+        assert_eq!(container.modify(id(2), |value| value.len()), Ok(1));
+        assert_eq!(container.modify(id(2), |value| value.push('#')), Ok(()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"c#".to_string()));
+        assert_eq!(container.modify(id(3), |_| ()), Err(Error::IdNotFound(3)));
+
+        let [a, c] = container.get_many_mut([id(0), id(2)]).unwrap();
+        std::mem::swap(a, c);
+        assert_eq!(container.get_from_id(id(0)), Some(&"c#".to_string()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"a!".to_string()));
+        assert!(container.get_many_mut([id(0), id(0)]).is_none());
+        assert!(container.get_many_mut([id(0), id(3)]).is_none());
+        container.remove(id(1)).unwrap();
+        assert!(container.get_many_mut([id(1), id(2)]).is_none());
+    }
+
+    /// Tests the 'take' and 'replace' methods of the Container struct to
+    /// ensure they hand back the removed or replaced value and keep the
+    /// swap-remove behavior of 'remove'.
+    #[test]
+    fn test_take_and_replace() {
+        let mut container = setup_container();
+        assert_eq!(
+            container.replace(id(1), "x".to_string()),
+            Ok("b".to_string())
+        );
+        assert_eq!(container.get_from_id(id(1)), Some(&"x".to_string()));
+        assert_eq!(
+            container.replace(id(3), "y".to_string()),
+            Err(Error::IdNotFound(3))
+        );
+
+        assert_eq!(container.take(id(0)), Ok("a".to_string()));
+        assert_eq!(container.take(id(0)), Err(Error::IdNotFound(0)));
+        assert_eq!(container.index_of_id(id(2)), Some(0));
+        assert_eq!(container.size(), 2);
+    }
+
+    /// Tests the 'remove_with_relocation' method of the Container struct to
+    /// ensure it reports the element that was swapped into the hole left by
+    /// the removed one, and nothing when the last element is removed.
+    #[test]
+    fn test_remove_with_relocation() {
+        let mut container = setup_container();
+        container.add("d".to_string()).unwrap();
+        assert_eq!(
+            container.remove_with_relocation(id(1)),
+            Ok(Removal {
+                removed_index: 1,
+                moved: Some((id(3), 3, 1)),
+            })
+        );
+        assert_eq!(container.index_of_id(id(3)), Some(1));
+        assert_eq!(
+            container.remove_with_relocation(id(2)),
+            Ok(Removal {
+                removed_index: 2,
+                moved: None,
+            })
+        );
+        assert_eq!(
+            container.remove_with_relocation(id(2)),
+            Err(Error::IdNotFound(2))
+        );
+        assert_eq!(container.ids().collect::<Vec<_>>(), vec![id(0), id(3)]);
+    }
+
+    /// Tests the serde implementations of the Container struct to ensure a
+    /// round trip preserves live ids, free ids and generations, so handles
+    /// created before serialization stay valid, and that inconsistent input
+    /// is rejected.
+    #[test]
+    fn test_serde() {
+        let mut container = setup_container();
+        let handle = container.add_with_handle("d".to_string()).unwrap();
+        container.remove(id(1)).unwrap();
+
+        let json = serde_json::to_string(&container).unwrap();
+        let mut restored: Container<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.data, container.data);
+        assert_eq!(restored.id, container.id);
+        assert_eq!(restored.data_index, container.data_index);
+        assert_eq!(restored.generation, container.generation);
+        assert_eq!(restored.get_from_handle(handle), Some(&"d".to_string()));
+        assert_eq!(restored.add("e".to_string()).unwrap(), id(1));
+        assert_eq!(restored.get_from_id(id(1)), Some(&"e".to_string()));
+
+        let handle_json = serde_json::to_string(&handle).unwrap();
+        let restored_handle: Handle<String> = serde_json::from_str(&handle_json).unwrap();
+        assert_eq!(restored_handle, handle);
+
+        let inconsistent = r#"{"data_index":[1,0],"id":[0,1],"data":["a","b"],"generation":[0,0]}"#;
+        assert!(serde_json::from_str::<Container<String>>(inconsistent).is_err());
+        let out_of_range = r#"{"data_index":[0,1],"id":[0,5],"data":["a","b"],"generation":[0,0]}"#;
+        assert!(serde_json::from_str::<Container<String>>(out_of_range).is_err());
+    }
+
+    /// Tests the entry API of the Container struct to ensure occupied
+    /// entries give access to their value, and that vacant entries insert
+    /// under the requested id, while ids that were never handed out are
+    /// rejected.
+    #[test]
+    fn test_entry() {
+        let mut container = setup_container();
+        container
+            .entry(id(0))
+            .unwrap()
+            .and_modify(|value| value.push('!'));
+        assert_eq!(container.get_from_id(id(0)), Some(&"a!".to_string()));
+        assert_eq!(
+            container.entry(id(1)).unwrap().or_insert("x".to_string()),
+            "b"
+        );
+        assert!(matches!(container.entry(id(1)), Ok(Entry::Occupied(_))));
+
+        container.remove(id(1)).unwrap();
+        assert!(matches!(container.entry(id(1)), Ok(Entry::Vacant(_))));
+        container
+            .entry(id(1))
+            .unwrap()
+            .and_modify(|value| value.push('?'))
+            .or_insert_with(|| "y".to_string());
+        assert_eq!(container.get_from_id(id(1)), Some(&"y".to_string()));
+
+        assert_eq!(container.entry(id(3)).err(), Some(Error::IdNotFound(3)));
+        assert_eq!(container.add("z".to_string()).unwrap(), id(3));
+        assert!(matches!(container.entry(id(3)), Ok(Entry::Occupied(_))));
+
+        if let Ok(Entry::Occupied(mut entry)) = container.entry(id(2)) {
+            assert_eq!(entry.id(), id(2));
+            assert_eq!(entry.insert("c2".to_string()), "c");
+            assert_eq!(entry.get(), "c2");
+            assert_eq!(entry.remove(), "c2");
+        }
+        assert_eq!(container.get_from_id(id(2)), None);
+        assert_eq!(container.entry(id(2)).unwrap().id(), id(2));
+    }
+
+    /// Tests the standard trait implementations of the Container struct to
+    /// ensure indexing by handle, collecting, extending, comparing, hashing
+    /// and debug formatting all work on the id to value mappings.
+    #[test]
+    fn test_standard_traits() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let hash = |container: &Container<String>| {
+            let mut hasher = DefaultHasher::new();
+            container.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let mut container: Container<String> =
+            ["a", "b", "c"].into_iter().map(String::from).collect();
+        let handle = container.handle_of(id(1)).unwrap();
+        container[handle].push('!');
+        assert_eq!(container[handle], "b!");
+        container[id(2)].push('?');
+        assert_eq!(container[id(2)], "c?");
+        container[id(2)].pop();
+
+        container.extend(["d".to_string()]);
+        assert_eq!(container.get_from_id(id(3)), Some(&"d".to_string()));
+        assert_eq!(
+            format!("{container:?}"),
+            r#"{0: "a", 1: "b!", 2: "c", 3: "d"}"#
+        );
+
+        let mut other = setup_container();
+        other.update(id(1), "b!".to_string()).unwrap();
+        other.add("d".to_string()).unwrap();
+        assert_eq!(container, other);
+        assert_eq!(hash(&container), hash(&other));
+
+        container.remove(id(0)).unwrap();
+        other.remove(id(0)).unwrap();
+        let taken = other.take(id(3)).unwrap();
+        other.entry(id(3)).unwrap().or_insert(taken);
+        assert_ne!(
+            container.ids().collect::<Vec<_>>(),
+            other.ids().collect::<Vec<_>>()
+        );
+        assert_eq!(container, other);
+        assert_eq!(hash(&container), hash(&other));
+
+        other.update(id(2), "x".to_string()).unwrap();
+        assert_ne!(container, other);
+    }
+
+    /// Tests a container with a narrow id type to ensure the ids are stored
+    /// in that type, that running out of ids is reported by 'add', and that
+    /// freed ids can still be recycled once the id type is exhausted.
+    #[test]
+    fn test_id_type() {
+        let mut container: Container<u32, u8> = Container::with_id_type();
+        for value in 0..256 {
+            container.add(value).unwrap();
+        }
+        assert_eq!(container.add(256), Err(Error::CapacityExhausted));
+
+        let last = container.get_id_from_index(255).unwrap();
+        assert_eq!(last.value(), u8::MAX);
+        container.remove(last).unwrap();
+        assert_eq!(container.add(256), Ok(last));
+        assert_eq!(container[last], 256);
+
+        let json = serde_json::to_string(&container).unwrap();
+        let restored: Container<u32, u8> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, container);
+    }
+
+    /// Tests the capacity methods of the Container struct to ensure space can
+    /// be reserved and released, and that 'compact_ids' renumbers the live
+    /// ids densely and reports the remap from old to new ids.
+    #[test]
+    fn test_capacity_and_compaction() {
+        let mut container: Container<String> = Container::with_capacity(8);
+        assert!(container.capacity() >= 8);
+        for value in ["a", "b", "c", "d", "e"] {
+            container.add(value.to_string()).unwrap();
+        }
+        container.reserve(10);
+        assert!(container.capacity() >= 15);
+
+        let handle = container.handle_of(id(4)).unwrap();
+        container.remove(id(0)).unwrap();
+        container.remove(id(2)).unwrap();
+        container.shrink_to_fit();
+        assert!(container.capacity() < 15);
+
+        let remap = container.compact_ids();
+        assert_eq!(
+            remap,
+            vec![None, Some(id(1)), None, Some(id(2)), Some(id(0))]
+        );
+        assert_eq!(
+            container.ids().collect::<Vec<_>>(),
+            vec![id(0), id(1), id(2)]
+        );
+        assert_eq!(container.get_from_id(id(0)), Some(&"e".to_string()));
+        assert_eq!(container.get_from_id(id(1)), Some(&"b".to_string()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"d".to_string()));
+        assert_eq!(container.get_from_id(id(3)), None);
+        assert_eq!(container.get_from_handle(handle), None);
+        assert_eq!(container.add("f".to_string()), Ok(id(3)));
+    }
+
+    /// Tests the bulk methods of the Container struct to ensure 'add_many'
+    /// recycles free ids before appending new ones, that 'remove_many'
+    /// reports every id it could not remove, and that running out of ids
+    /// leaves the container untouched.
+    #[test]
+    fn test_bulk_operations() {
+        let mut container = setup_container();
+        container.remove(id(1)).unwrap();
+        assert_eq!(
+            container.add_many(["d", "e", "f"].map(String::from)),
+            Ok(vec![id(1), id(3), id(4)])
+        );
+        assert_eq!(container.size(), 5);
+        assert_eq!(container.get_from_id(id(1)), Some(&"d".to_string()));
+        assert_eq!(container.get_from_id(id(4)), Some(&"f".to_string()));
+
+        assert_eq!(
+            container.remove_many(&[id(0), id(9), id(3), id(0)]),
+            Err(vec![Error::IdNotFound(9), Error::IdNotFound(0)])
+        );
+        assert_eq!(container.size(), 3);
+        assert_eq!(container.remove_many(&[id(1)]), Ok(()));
+
+        assert_eq!(
+            container.add_many(["g", "h"].map(String::from)),
+            Ok(vec![id(1), id(3)])
+        );
+        assert_eq!(container.get_from_id(id(3)), Some(&"h".to_string()));
+
+        let mut narrow: Container<u32, u8> = Container::with_id_type();
+        assert_eq!(narrow.add_many(vec![0; 300]), Err(Error::CapacityExhausted));
+        assert!(narrow.empty());
+        assert_eq!(narrow.add_many(vec![0; 256]).map(|ids| ids.len()), Ok(256));
+    }
+
+    /// Tests the 'retain' and 'drain_filter' methods of the Container struct
+    /// to ensure matching elements are removed in a single pass, that the
+    /// predicate can modify kept elements, and that the ids of the remaining
+    /// elements stay valid.
+    #[test]
+    fn test_retain_and_drain_filter() {
+        let mut container = setup_container();
+        container.add_many(["d", "e"].map(String::from)).unwrap();
+        container.retain(|id, data| {
+            data.push('!');
+            id.value() % 2 == 0
+        });
+        assert_eq!(container.size(), 3);
+        assert_eq!(container.get_from_id(id(0)), Some(&"a!".to_string()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"c!".to_string()));
+        assert_eq!(container.get_from_id(id(4)), Some(&"e!".to_string()));
+        assert!(!container.contains_id(id(1)));
+        assert!(!container.contains_id(id(3)));
+
+        let mut drained: Vec<_> = container
+            .drain_filter(|_, data| data.as_str() != "c!")
+            .collect();
+        drained.sort();
+        assert_eq!(
+            drained,
+            vec![(id(0), "a!".to_string()), (id(4), "e!".to_string())]
+        );
+        assert_eq!(container.ids().collect::<Vec<_>>(), vec![id(2)]);
+
+        container.add_many(["f", "g"].map(String::from)).unwrap();
+        assert!(container.drain_filter(|_, _| true).next().is_some());
+        assert_eq!(container.size(), 2);
+    }
+
+    /// Tests the 'sort_by', 'sort_by_key' and 'sort_unstable_by' methods of
+    /// the Container struct to ensure the values are reordered in the 'data'
+    /// vector while every id still resolves to its own value.
+    #[test]
+    fn test_sort_by() {
+        let mut container = setup_container();
+        container.add_many(["e", "d"].map(String::from)).unwrap();
+        container.remove(id(1)).unwrap();
+
+        container.sort_by(|a, b| b.cmp(a));
+        assert_eq!(
+            container.values().collect::<Vec<_>>(),
+            vec!["e", "d", "c", "a"]
+        );
+        assert_eq!(
+            container.ids().collect::<Vec<_>>(),
+            vec![id(3), id(4), id(2), id(0)]
+        );
+        for (id, value) in container.iter_with_ids() {
+            assert_eq!(
+                container.index_of_id(id).and_then(|i| container.get(i)),
+                Some(value)
+            );
+        }
+
+        container.sort_by_key(|value| value.clone());
+        assert_eq!(
+            container.values().collect::<Vec<_>>(),
+            vec!["a", "c", "d", "e"]
+        );
+        assert_eq!(container.get_from_id(id(4)), Some(&"d".to_string()));
+
+        container.sort_unstable_by(|a, b| b.cmp(a));
+        assert_eq!(
+            container.values().collect::<Vec<_>>(),
+            vec!["e", "d", "c", "a"]
+        );
+        assert_eq!(container.get_from_id(id(3)), Some(&"e".to_string()));
+        assert_eq!(container.add("f".to_string()), Ok(id(1)));
+    }
 }
@@ -0,0 +1,67 @@
+use std::fmt;
+
+/// Errors returned by the operations of the containers in this crate. The
+/// type parameter is the reference type of the container, which is carried
+/// by 'ReferenceNotFound' and defaults to `usize`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Error<R = usize> {
+    /// The id is not associated with a value in the container.
+    IdNotFound(usize),
+    /// The index does not point to a value stored in the container.
+    IndexOutOfBounds(usize),
+    /// No value in the container is associated with the reference.
+    ReferenceNotFound(R),
+    /// The handle points to an id that has been freed since the handle was
+    /// created.
+    StaleHandle,
+    /// The container has run out of ids to hand out.
+    CapacityExhausted,
+}
+
+impl<R: fmt::Debug> fmt::Display for Error<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::IdNotFound(id) => write!(f, "ID {id} not found in the container"),
+            Error::IndexOutOfBounds(index) => write!(f, "Index {index} out of bounds"),
+            Error::ReferenceNotFound(reference) => {
+                write!(f, "Reference {reference:?} not found in the container")
+            }
+            Error::StaleHandle => write!(f, "Stale handle"),
+            Error::CapacityExhausted => write!(f, "Container capacity exhausted"),
+        }
+    }
+}
+
+impl<R: fmt::Debug> std::error::Error for Error<R> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests the 'Display' implementation of the Error enum to ensure every
+    /// variant produces a readable message that includes its payload.
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            Error::<usize>::IdNotFound(3).to_string(),
+            "ID 3 not found in the container"
+        );
+        assert_eq!(
+            Error::<usize>::IndexOutOfBounds(7).to_string(),
+            "Index 7 out of bounds"
+        );
+        assert_eq!(
+            Error::ReferenceNotFound(2).to_string(),
+            "Reference 2 not found in the container"
+        );
+        assert_eq!(
+            Error::ReferenceNotFound("a").to_string(),
+            "Reference \"a\" not found in the container"
+        );
+        assert_eq!(Error::<usize>::StaleHandle.to_string(), "Stale handle");
+        assert_eq!(
+            Error::<usize>::CapacityExhausted.to_string(),
+            "Container capacity exhausted"
+        );
+    }
+}
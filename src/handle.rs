@@ -0,0 +1,66 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::id::{Id, IdInt};
+
+/// A generational handle to an element of a container. The handle pairs the
+/// id of the element with the generation of that id at the time the handle
+/// was created. Every time an id is freed its generation is increased, so a
+/// handle kept from before a removal no longer matches once the id has been
+/// recycled for a new element.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "I: IdInt")]
+pub struct Handle<T, I = usize> {
+    id: Id<T, I>,
+    generation: u32,
+}
+
+impl<T, I: IdInt> Handle<T, I> {
+    pub(crate) fn new(id: Id<T, I>, generation: u32) -> Self {
+        Handle { id, generation }
+    }
+
+    /// Returns the id the handle points to.
+    pub fn id(&self) -> Id<T, I> {
+        self.id
+    }
+
+    /// Returns the generation of the id at the time the handle was created.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl<T, I: IdInt> Clone for Handle<T, I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, I: IdInt> Copy for Handle<T, I> {}
+
+impl<T, I: IdInt> PartialEq for Handle<T, I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.generation == other.generation
+    }
+}
+
+impl<T, I: IdInt> Eq for Handle<T, I> {}
+
+impl<T, I: IdInt> Hash for Handle<T, I> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T, I: IdInt> fmt::Debug for Handle<T, I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("id", &self.id)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
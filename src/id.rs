@@ -0,0 +1,151 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Unsigned integer types that can be used to store the ids of a container.
+/// Smaller types shrink the bookkeeping kept for every element, at the cost
+/// of a lower limit on the number of ids a container can hand out.
+pub trait IdInt: Copy + Ord + Hash + fmt::Debug + Serialize + DeserializeOwned {
+    /// Converts a bare `usize` value into this type, or returns `None` if
+    /// the value does not fit.
+    fn from_usize(value: usize) -> Option<Self>;
+
+    /// Converts the value into a bare `usize`.
+    fn to_usize(self) -> usize;
+}
+
+macro_rules! impl_id_int {
+    ($($int:ty),*) => {
+        $(
+            impl IdInt for $int {
+                fn from_usize(value: usize) -> Option<Self> {
+                    <$int>::try_from(value).ok()
+                }
+
+                fn to_usize(self) -> usize {
+                    self as usize
+                }
+            }
+        )*
+    };
+}
+
+impl_id_int!(u8, u16, u32, u64, usize);
+
+/// A typed id of an element stored in a container of `T` values. The id is a
+/// zero-cost wrapper around the bare integer id: it only exists so that ids
+/// of containers holding different value types cannot be mixed up, and so
+/// that ids cannot be confused with the dense indices of the 'data' vector,
+/// which remain plain `usize` values. The integer type `I` matches the id
+/// type of the container the id belongs to.
+pub struct Id<T, I = usize> {
+    value: I,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T, I: IdInt> Id<T, I> {
+    /// Creates a typed id from its bare value.
+    pub const fn new(value: I) -> Self {
+        Id {
+            value,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the bare value of the id.
+    pub const fn value(self) -> I {
+        self.value
+    }
+
+    /// Returns the bare value of the id converted to a `usize`.
+    pub fn to_usize(self) -> usize {
+        self.value.to_usize()
+    }
+}
+
+impl<T, I: IdInt> Clone for Id<T, I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, I: IdInt> Copy for Id<T, I> {}
+
+impl<T, I: IdInt> PartialEq for Id<T, I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T, I: IdInt> Eq for Id<T, I> {}
+
+impl<T, I: IdInt> PartialOrd for Id<T, I> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T, I: IdInt> Ord for Id<T, I> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.cmp(&other.value)
+    }
+}
+
+impl<T, I: IdInt> Hash for Id<T, I> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+    }
+}
+
+impl<T, I: IdInt> fmt::Debug for Id<T, I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Id({:?})", self.value)
+    }
+}
+
+impl<T, I: IdInt> Serialize for Id<T, I> {
+    /// Serializes the id as its bare value.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(serializer)
+    }
+}
+
+impl<'de, T, I: IdInt> Deserialize<'de> for Id<T, I> {
+    /// Deserializes the id from its bare value.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        I::deserialize(deserializer).map(Id::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that ids compare and order by their bare value, and that they
+    /// serialize as the bare value so persisted ids stay readable.
+    #[test]
+    fn test_id() {
+        let id: Id<String> = Id::new(3);
+        assert_eq!(id.value(), 3);
+        assert_eq!(id, Id::new(3));
+        assert!(id < Id::new(4));
+        assert_eq!(format!("{id:?}"), "Id(3)");
+        assert_eq!(serde_json::to_string(&id).unwrap(), "3");
+        assert_eq!(serde_json::from_str::<Id<String>>("3").unwrap(), id);
+    }
+
+    /// Tests the conversions of the 'IdInt' implementations to ensure values
+    /// that do not fit into the smaller id types are rejected.
+    #[test]
+    fn test_id_int() {
+        assert_eq!(u16::from_usize(65_535), Some(u16::MAX));
+        assert_eq!(u16::from_usize(65_536), None);
+        assert_eq!(u8::from_usize(7).map(IdInt::to_usize), Some(7));
+        let id: Id<String, u32> = Id::new(9);
+        assert_eq!(id.to_usize(), 9);
+    }
+}
@@ -1,2 +1,11 @@
+pub mod column_container;
 pub mod container;
+pub mod error;
+pub mod handle;
+pub mod id;
+pub mod multi_reference_container;
 pub mod reference_container;
+pub mod removal;
+
+pub use error::Error;
+pub use id::Id;
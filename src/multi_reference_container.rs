@@ -0,0 +1,559 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::container::Container;
+use crate::error::Error;
+use crate::id::{Id, IdInt};
+
+/// Decides what happens to an element of a 'MultiReferenceContainer' when
+/// the last of its references is removed with 'remove_reference'.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OrphanPolicy {
+    /// The element stays in the container without any reference.
+    #[default]
+    Keep,
+    /// The element is removed from the container together with its last
+    /// reference.
+    Remove,
+}
+
+/// A container in which every element can be associated with any number of
+/// references, and every reference with any number of elements.
+///
+/// The values and their ids are stored in an inner 'Container', so ids are
+/// recycled and stay stable in the same way. The 'references' vector is
+/// indexed by id and holds the references of every live id, without
+/// duplicates, and is empty for free ids. The 'reference_index' map groups
+/// the live ids by reference, so the elements of a reference can be found
+/// without scanning the container. The 'group_index' vector is indexed by id
+/// in the same way as 'references', and holds the position of the id inside
+/// the group of each of its references, so an id can be dropped from a
+/// group in constant time. Both are derived from the 'references' vector
+/// and are not serialized.
+#[derive(Serialize)]
+pub struct MultiReferenceContainer<T, I = usize> {
+    container: Container<T, I>,
+    references: Vec<Vec<usize>>,
+    #[serde(skip)]
+    reference_index: HashMap<usize, Vec<I>>,
+    #[serde(skip)]
+    group_index: Vec<Vec<I>>,
+    policy: OrphanPolicy,
+}
+
+impl<T: Clone, I: IdInt> Clone for MultiReferenceContainer<T, I> {
+    fn clone(&self) -> Self {
+        MultiReferenceContainer {
+            container: self.container.clone(),
+            references: self.references.clone(),
+            reference_index: self.reference_index.clone(),
+            group_index: self.group_index.clone(),
+            policy: self.policy,
+        }
+    }
+}
+
+impl<T, I: IdInt> Default for MultiReferenceContainer<T, I> {
+    fn default() -> Self {
+        Self::with_id_type()
+    }
+}
+
+impl<T> MultiReferenceContainer<T> {
+    /// Creates an empty container that stores its ids as `usize` values.
+    pub fn new() -> Self {
+        Self::with_id_type()
+    }
+
+    /// Creates an empty container that stores its ids as `usize` values,
+    /// with space for at least `capacity` elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_id_type(capacity)
+    }
+}
+
+impl<T, I: IdInt> MultiReferenceContainer<T, I> {
+    /// Creates an empty container that stores its ids as values of the id
+    /// type `I`, keeping elements that lose their last reference.
+    pub fn with_id_type() -> Self {
+        MultiReferenceContainer {
+            container: Container::with_id_type(),
+            references: Vec::new(),
+            reference_index: HashMap::new(),
+            group_index: Vec::new(),
+            policy: OrphanPolicy::Keep,
+        }
+    }
+
+    /// Creates an empty container that stores its ids as values of the id
+    /// type `I`, with space for at least `capacity` elements.
+    pub fn with_capacity_and_id_type(capacity: usize) -> Self {
+        MultiReferenceContainer {
+            container: Container::with_capacity_and_id_type(capacity),
+            references: Vec::with_capacity(capacity),
+            reference_index: HashMap::new(),
+            group_index: Vec::new(),
+            policy: OrphanPolicy::Keep,
+        }
+    }
+
+    /// Returns the policy applied to elements that lose their last
+    /// reference.
+    pub fn orphan_policy(&self) -> OrphanPolicy {
+        self.policy
+    }
+
+    /// Sets the policy applied to elements that lose their last reference.
+    /// Elements that have no references when the policy is changed are kept.
+    pub fn set_orphan_policy(&mut self, policy: OrphanPolicy) {
+        self.policy = policy;
+    }
+
+    /// Finds the value associated with the given id and returns a reference
+    /// to it. Returns `None` if the id is not found in the container.
+    pub fn get_from_id(&self, id: Id<T, I>) -> Option<&T> {
+        self.container.get_from_id(id)
+    }
+
+    /// Finds the value associated with the given id and returns a mutable
+    /// reference to it. Returns `None` if the id is not found in the
+    /// container.
+    pub fn get_mut_from_id(&mut self, id: Id<T, I>) -> Option<&mut T> {
+        self.container.get_mut_from_id(id)
+    }
+
+    /// Checks whether the given id is associated with a value in the
+    /// container.
+    pub fn contains_id(&self, id: Id<T, I>) -> bool {
+        self.container.contains_id(id)
+    }
+
+    /// Adds a new element with the given references to the container and
+    /// returns its id. Duplicate references are only recorded once. Returns
+    /// `Error::CapacityExhausted` if a new id is needed but does not fit
+    /// into the id type of the container.
+    pub fn add(
+        &mut self,
+        data: T,
+        references: impl IntoIterator<Item = usize>,
+    ) -> Result<Id<T, I>, Error> {
+        let id = self.container.add(data)?;
+        if self.references.len() <= id.to_usize() {
+            self.references.resize_with(id.to_usize() + 1, Vec::new);
+            self.group_index.resize_with(id.to_usize() + 1, Vec::new);
+        }
+        for reference in references {
+            self.add_reference(id, reference)?;
+        }
+        Ok(id)
+    }
+
+    /// Removes the value associated with the given id, together with all of
+    /// its references. Returns `Error::IdNotFound` if the id is not found in
+    /// the container.
+    pub fn remove(&mut self, id: Id<T, I>) -> Result<(), Error> {
+        self.take(id)?;
+        Ok(())
+    }
+
+    /// Removes the value associated with the given id in the same way as
+    /// 'remove', but returns the removed value instead of dropping it.
+    pub fn take(&mut self, id: Id<T, I>) -> Result<T, Error> {
+        let data = self.container.take(id)?;
+        let references = std::mem::take(&mut self.references[id.to_usize()]);
+        let positions = std::mem::take(&mut self.group_index[id.to_usize()]);
+        for (reference, position) in references.into_iter().zip(positions) {
+            self.unindex_reference(reference, position);
+        }
+        Ok(data)
+    }
+
+    /// Associates the value of the given id with the given reference, in
+    /// addition to its other references. Returns `Ok(true)` if the reference
+    /// was added, `Ok(false)` if the value already had it, or
+    /// `Error::IdNotFound` if the id is not found in the container.
+    pub fn add_reference(&mut self, id: Id<T, I>, reference: usize) -> Result<bool, Error> {
+        if !self.contains_id(id) {
+            return Err(Error::IdNotFound(id.to_usize()));
+        }
+        let references = &mut self.references[id.to_usize()];
+        if references.contains(&reference) {
+            return Ok(false);
+        }
+        references.push(reference);
+        self.index_reference(id.value(), reference);
+        Ok(true)
+    }
+
+    /// Removes the given reference from the value of the given id. If this
+    /// was the last reference of the value and the orphan policy is
+    /// `OrphanPolicy::Remove`, the value is removed from the container and
+    /// returned as `Ok(Some(T))`, otherwise `Ok(None)` is returned. Returns
+    /// `Error::IdNotFound` if the id is not found in the container, or
+    /// `Error::ReferenceNotFound` if the value does not have the reference.
+    pub fn remove_reference(
+        &mut self,
+        id: Id<T, I>,
+        reference: &usize,
+    ) -> Result<Option<T>, Error> {
+        if !self.contains_id(id) {
+            return Err(Error::IdNotFound(id.to_usize()));
+        }
+        let references = &mut self.references[id.to_usize()];
+        let position = references
+            .iter()
+            .position(|other| other == reference)
+            .ok_or(Error::ReferenceNotFound(*reference))?;
+        references.swap_remove(position);
+        let orphaned = references.is_empty();
+        let group_position = self.group_index[id.to_usize()].swap_remove(position);
+        self.unindex_reference(*reference, group_position);
+
+        if orphaned && self.policy == OrphanPolicy::Remove {
+            self.take(id).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the references of the value associated with the given id, in
+    /// no particular order. Returns `None` if the id is not found in the
+    /// container.
+    pub fn references_of(&self, id: Id<T, I>) -> Option<&[usize]> {
+        if self.contains_id(id) {
+            Some(&self.references[id.to_usize()])
+        } else {
+            None
+        }
+    }
+
+    /// Returns an iterator over the ids associated with the given reference,
+    /// in no particular order. The iterator is empty if the reference is not
+    /// found in the container.
+    pub fn ids_by_reference(&self, reference: &usize) -> impl Iterator<Item = Id<T, I>> + '_ {
+        self.reference_index
+            .get(reference)
+            .into_iter()
+            .flatten()
+            .map(|&id| Id::new(id))
+    }
+
+    /// Returns the number of values associated with the given reference.
+    pub fn count_by_reference(&self, reference: &usize) -> usize {
+        self.reference_index.get(reference).map_or(0, Vec::len)
+    }
+
+    /// Returns an iterator over the distinct references of the values in the
+    /// container, in no particular order.
+    pub fn references(&self) -> impl Iterator<Item = usize> + '_ {
+        self.reference_index.keys().copied()
+    }
+
+    /// Records the given live id under the given reference in the
+    /// 'reference_index' map, and its position inside the group in the
+    /// 'group_index' vector. The reference must be the last one in the
+    /// 'references' of the id.
+    fn index_reference(&mut self, id: I, reference: usize) {
+        let ids = self.reference_index.entry(reference).or_default();
+        self.group_index[id.to_usize()]
+            .push(I::from_usize(ids.len()).expect("This should never fail"));
+        ids.push(id);
+    }
+
+    /// Removes the id at the given position from the group of the given
+    /// reference in the 'reference_index' map, dropping the group once it is
+    /// empty. The last id of the group takes the place of the removed one,
+    /// so only its entry in 'group_index' has to be updated, which is found
+    /// among the few references of that id.
+    fn unindex_reference(&mut self, reference: usize, position: I) {
+        let ids = self
+            .reference_index
+            .get_mut(&reference)
+            .expect("This should never fail");
+        ids.swap_remove(position.to_usize());
+        if let Some(&moved) = ids.get(position.to_usize()) {
+            let slot = self.references[moved.to_usize()]
+                .iter()
+                .position(|&other| other == reference)
+                .expect("This should never fail");
+            self.group_index[moved.to_usize()][slot] = position;
+        }
+        if ids.is_empty() {
+            self.reference_index.remove(&reference);
+        }
+    }
+
+    /// Rebuilds the 'reference_index' map and the 'group_index' vector from
+    /// the 'references' vector.
+    fn rebuild_reference_index(&mut self) {
+        self.reference_index.clear();
+        self.group_index.clear();
+        self.group_index
+            .resize_with(self.references.len(), Vec::new);
+        for id in 0..self.references.len() {
+            for slot in 0..self.references[id].len() {
+                let reference = self.references[id][slot];
+                self.index_reference(
+                    I::from_usize(id).expect("This should never fail"),
+                    reference,
+                );
+            }
+        }
+    }
+
+    /// Checks that the 'references' vector is consistent with the ids of the
+    /// inner container: every live id has an entry, free ids have no
+    /// references, and no value has the same reference twice.
+    fn validate(&self) -> Result<(), &'static str> {
+        for id in self.container.ids() {
+            if id.to_usize() >= self.references.len() {
+                return Err("references vector is missing a live id");
+            }
+        }
+        for (id, references) in self.references.iter().enumerate() {
+            let live = I::from_usize(id).is_some_and(|id| self.contains_id(Id::new(id)));
+            if !live && !references.is_empty() {
+                return Err("references vector has references for a free id");
+            }
+            for (index, reference) in references.iter().enumerate() {
+                if references[index + 1..].contains(reference) {
+                    return Err("references vector has duplicate references");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over the ids and references to the values in the
+    /// container, in the order the values are stored.
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (Id<T, I>, &T)> {
+        self.container.iter_with_ids()
+    }
+
+    /// Returns an iterator over the ids of the values in the container, in
+    /// the order the values are stored.
+    pub fn ids(&self) -> impl Iterator<Item = Id<T, I>> {
+        self.container.ids()
+    }
+
+    /// Returns the number of elements in the container.
+    pub fn size(&self) -> usize {
+        self.container.size()
+    }
+
+    /// Returns true if the container is empty, false otherwise.
+    pub fn empty(&self) -> bool {
+        self.container.empty()
+    }
+
+    /// Clears all elements and references from the container in the same
+    /// way as 'Container::clear'.
+    pub fn clear(&mut self) {
+        self.container.clear();
+        self.references.clear();
+        self.reference_index.clear();
+        self.group_index.clear();
+    }
+}
+
+impl<'de, T: Deserialize<'de>, I: IdInt> Deserialize<'de> for MultiReferenceContainer<T, I> {
+    /// Deserializes the inner container and the references of its values,
+    /// then rebuilds the 'reference_index' map. References that do not match
+    /// the ids of the inner container are rejected.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "MultiReferenceContainer")]
+        #[serde(bound(deserialize = "T: Deserialize<'de>, I: IdInt"))]
+        struct Fields<T, I> {
+            container: Container<T, I>,
+            references: Vec<Vec<usize>>,
+            policy: OrphanPolicy,
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        let mut container = MultiReferenceContainer {
+            container: fields.container,
+            references: fields.references,
+            reference_index: HashMap::new(),
+            group_index: Vec::new(),
+            policy: fields.policy,
+        };
+        container.validate().map_err(D::Error::custom)?;
+        container.rebuild_reference_index();
+        Ok(container)
+    }
+}
+
+impl<T: fmt::Debug, I: IdInt> fmt::Debug for MultiReferenceContainer<T, I> {
+    /// Formats the container as a map from ids to their references and
+    /// values, in the order the values are stored.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.iter_with_ids()
+                    .map(|(id, data)| (id.value(), (&self.references[id.to_usize()], data))),
+            )
+            .finish()
+    }
+}
+
+impl<T, I: IdInt> Index<Id<T, I>> for MultiReferenceContainer<T, I> {
+    type Output = T;
+
+    /// Returns a reference to the value associated with the given id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id is not found in the container.
+    fn index(&self, id: Id<T, I>) -> &T {
+        &self.container[id]
+    }
+}
+
+impl<T, I: IdInt> IndexMut<Id<T, I>> for MultiReferenceContainer<T, I> {
+    /// Returns a mutable reference to the value associated with the given id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id is not found in the container.
+    fn index_mut(&mut self, id: Id<T, I>) -> &mut T {
+        &mut self.container[id]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an id for the values of the test containers.
+    fn id(value: usize) -> Id<String> {
+        Id::new(value)
+    }
+
+    /// Creates a container with three values, where "a" is referenced by 0
+    /// and 1, "b" by 1, and "c" by nothing.
+    fn setup_container() -> MultiReferenceContainer<String> {
+        let mut container = MultiReferenceContainer::new();
+        container.add("a".to_string(), [0, 1]).unwrap();
+        container.add("b".to_string(), [1]).unwrap();
+        container.add("c".to_string(), []).unwrap();
+        container
+    }
+
+    /// Tests the reference methods of the MultiReferenceContainer struct to
+    /// ensure values can belong to several references at once, duplicate
+    /// references are ignored, and the reference index follows additions
+    /// and removals of both references and values.
+    #[test]
+    fn test_references() {
+        let mut container = setup_container();
+        assert_eq!(container.references_of(id(0)), Some(&[0, 1][..]));
+        assert_eq!(container.references_of(id(2)), Some(&[][..]));
+        assert_eq!(container.references_of(id(3)), None);
+
+        let mut ids: Vec<_> = container.ids_by_reference(&1).collect();
+        ids.sort();
+        assert_eq!(ids, vec![id(0), id(1)]);
+        assert_eq!(container.ids_by_reference(&7).count(), 0);
+
+        assert_eq!(container.add_reference(id(2), 0), Ok(true));
+        assert_eq!(container.add_reference(id(2), 0), Ok(false));
+        assert_eq!(container.add_reference(id(5), 0), Err(Error::IdNotFound(5)));
+        assert_eq!(container.count_by_reference(&0), 2);
+
+        assert_eq!(container.remove_reference(id(0), &0), Ok(None));
+        assert_eq!(
+            container.remove_reference(id(0), &0),
+            Err(Error::ReferenceNotFound(0))
+        );
+        assert_eq!(
+            container.ids_by_reference(&0).collect::<Vec<_>>(),
+            vec![id(2)]
+        );
+
+        assert_eq!(container.remove(id(1)), Ok(()));
+        assert_eq!(
+            container.ids_by_reference(&1).collect::<Vec<_>>(),
+            vec![id(0)]
+        );
+        let mut references: Vec<_> = container.references().collect();
+        references.sort();
+        assert_eq!(references, vec![0, 1]);
+
+        assert_eq!(container.add("d".to_string(), [4, 4]), Ok(id(1)));
+        assert_eq!(container.references_of(id(1)), Some(&[4][..]));
+    }
+
+    /// Tests the orphan policies of the MultiReferenceContainer struct to
+    /// ensure values are only removed with their last reference when the
+    /// policy asks for it.
+    #[test]
+    fn test_orphan_policy() {
+        let mut container = setup_container();
+        assert_eq!(container.orphan_policy(), OrphanPolicy::Keep);
+        assert_eq!(container.remove_reference(id(1), &1), Ok(None));
+        assert!(container.contains_id(id(1)));
+
+        container.set_orphan_policy(OrphanPolicy::Remove);
+        assert_eq!(container.remove_reference(id(0), &0), Ok(None));
+        assert_eq!(
+            container.remove_reference(id(0), &1),
+            Ok(Some("a".to_string()))
+        );
+        assert!(!container.contains_id(id(0)));
+        assert!(container.contains_id(id(1)));
+        assert_eq!(container.references().count(), 0);
+        assert_eq!(container.size(), 2);
+    }
+
+    /// Tests the removal of values from a large group of the
+    /// MultiReferenceContainer struct to ensure single removals keep the
+    /// group and the other references of the values consistent, without
+    /// scanning the group for every removed value.
+    #[test]
+    fn test_remove_large_reference_group() {
+        let mut container = MultiReferenceContainer::new();
+        for value in 0..100_000 {
+            container.add(value, [1, value % 3]).unwrap();
+        }
+        for value in (0..100_000).step_by(2) {
+            container.remove(Id::new(value)).unwrap();
+        }
+        assert_eq!(container.count_by_reference(&1), 50_000);
+        assert_eq!(container.count_by_reference(&0), 16_667);
+
+        for value in (1..100_000).step_by(2) {
+            assert_eq!(container.remove_reference(Id::new(value), &1), Ok(None));
+        }
+        assert_eq!(container.count_by_reference(&1), 0);
+        assert_eq!(container.ids_by_reference(&0).count(), 16_667);
+        assert!(container.ids_by_reference(&2).all(|id| id.value() % 6 == 5));
+        assert_eq!(container.size(), 50_000);
+    }
+
+    /// Tests the serialization of the MultiReferenceContainer struct to
+    /// ensure the references survive a round trip and the reference index is
+    /// rebuilt, and that references of free ids are rejected.
+    #[test]
+    fn test_serde() {
+        let mut container = setup_container();
+        container.remove(id(1)).unwrap();
+        container.set_orphan_policy(OrphanPolicy::Remove);
+
+        let json = serde_json::to_string(&container).unwrap();
+        let restored: MultiReferenceContainer<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.references, container.references);
+        assert_eq!(restored.reference_index, container.reference_index);
+        assert_eq!(restored.group_index, container.group_index);
+        assert_eq!(restored.orphan_policy(), OrphanPolicy::Remove);
+        assert_eq!(restored[id(0)], "a");
+
+        let free_reference = json.replace("[[0,1],[],[]]", "[[0,1],[3],[]]");
+        assert_ne!(free_reference, json);
+        assert!(serde_json::from_str::<MultiReferenceContainer<String>>(&free_reference).is_err());
+    }
+}
@@ -1,29 +1,122 @@
-#[derive(Default, Debug)]
-pub struct ReferenceContainer<T> {
-    data_index: Vec<usize>,
-    id: Vec<usize>,
+use std::borrow::Borrow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Index, IndexMut, Range, RangeBounds};
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::error::Error;
+use crate::handle::Handle;
+use crate::id::{Id, IdInt};
+use crate::removal::Removal;
+
+/// A container that stores its values densely in the 'data' vector and hands
+/// out stable ids to refer to them.
+///
+/// The 'id' vector is split into two partitions: the first `data.len()`
+/// entries are the live ids, in the same order as the values in 'data', and
+/// the remaining entries are the free ids, which are recycled by 'add'. The
+/// 'data_index' vector maps every id, live or free, to its position in the
+/// 'id' vector, and an id is only considered live if that position falls
+/// inside the live partition.
+///
+/// The 'reference' vector follows the live partition, holding the reference
+/// of every live value at the same index as the value itself. References
+/// can be of any type `R` that is ordered, hashable and cloneable, such as
+/// integers, strings, enums or the ids of another container. The
+/// 'reference_index' map groups the live ids by their reference, ordered by
+/// reference, so the elements of a reference or of a range of references can
+/// be found without scanning the container. The 'group_index' vector maps
+/// every live id to its position inside its group, so an id can be dropped
+/// from its group in constant time. Both are derived from the other vectors
+/// and are not serialized.
+///
+/// The 'sorted' flag is set by the sorts by reference and cleared by every
+/// operation that may break that order, such as adding or removing values.
+/// Only while it is set do 'set_reference' and 'move_reference' move values
+/// around to keep the container sorted. It is not serialized either, so a
+/// deserialized container has to be sorted again.
+#[derive(Serialize)]
+pub struct ReferenceContainer<T, I = usize, R = usize> {
+    data_index: Vec<I>,
+    id: Vec<I>,
     data: Vec<T>,
-    reference: Vec<usize>,
+    generation: Vec<u32>,
+    reference: Vec<R>,
+    #[serde(skip)]
+    reference_index: BTreeMap<R, Vec<I>>,
+    #[serde(skip)]
+    group_index: Vec<I>,
+    #[serde(skip)]
+    sorted: bool,
 }
 
-impl<T: Clone> Clone for ReferenceContainer<T> {
+impl<T: Clone, I: IdInt, R: Clone> Clone for ReferenceContainer<T, I, R> {
     fn clone(&self) -> Self {
         ReferenceContainer {
             data_index: self.data_index.clone(),
             id: self.id.clone(),
             data: self.data.clone(),
+            generation: self.generation.clone(),
             reference: self.reference.clone(),
+            reference_index: self.reference_index.clone(),
+            group_index: self.group_index.clone(),
+            sorted: self.sorted,
         }
     }
 }
 
-impl<T> ReferenceContainer<T> {
+impl<T, I: IdInt, R: Ord + Hash + Clone> Default for ReferenceContainer<T, I, R> {
+    fn default() -> Self {
+        Self::with_id_type()
+    }
+}
+
+impl<T, R: Ord + Hash + Clone> ReferenceContainer<T, usize, R> {
+    /// Creates an empty container that stores its ids as `usize` values.
     pub fn new() -> Self {
+        Self::with_id_type()
+    }
+
+    /// Creates an empty container that stores its ids as `usize` values,
+    /// with space for at least `capacity` elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_id_type(capacity)
+    }
+}
+
+impl<T, I: IdInt, R: Ord + Hash + Clone> ReferenceContainer<T, I, R> {
+    /// Creates an empty container that stores its ids as values of the id
+    /// type `I`. Smaller id types shrink the bookkeeping kept for every
+    /// element, but limit the number of ids the container can hand out.
+    pub fn with_id_type() -> Self {
         ReferenceContainer {
             data_index: Vec::new(),
             id: Vec::new(),
             data: Vec::new(),
+            generation: Vec::new(),
             reference: Vec::new(),
+            reference_index: BTreeMap::new(),
+            group_index: Vec::new(),
+            sorted: false,
+        }
+    }
+
+    /// Creates an empty container that stores its ids as values of the id
+    /// type `I`, with space for at least `capacity` elements.
+    pub fn with_capacity_and_id_type(capacity: usize) -> Self {
+        ReferenceContainer {
+            data_index: Vec::with_capacity(capacity),
+            id: Vec::with_capacity(capacity),
+            data: Vec::with_capacity(capacity),
+            generation: Vec::with_capacity(capacity),
+            reference: Vec::with_capacity(capacity),
+            reference_index: BTreeMap::new(),
+            group_index: Vec::with_capacity(capacity),
+            sorted: false,
         }
     }
 
@@ -36,90 +129,609 @@ impl<T> ReferenceContainer<T> {
     /// Finds the value associated with the given id and returns a reference
     /// to it. Returns `None` if the id is not found in the container.
     ///
-    /// The method works by first searching for the index of the provided id
-    /// in the 'id' vector, and then using that index to retrieve the
+    /// The method works by first looking up the index of the provided id
+    /// in the 'data_index' vector, and then using that index to retrieve the
     /// corresponding value from the 'data' vector.
-    pub fn get_from_id(&self, id: usize) -> Option<&T> {
-        self.id
-            .iter()
-            .position(|&x| x == id)
-            .and_then(|index| self.data.get(index))
+    pub fn get_from_id(&self, id: Id<T, I>) -> Option<&T> {
+        self.index_of_id(id).and_then(|index| self.data.get(index))
+    }
+
+    /// Retrieves a mutable reference to the value at the given index. Returns
+    /// `Some(&mut T)` if the index is valid, or `None` if the index is out of
+    /// bounds.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.data.get_mut(index)
+    }
+
+    /// Finds the value associated with the given id and returns a mutable
+    /// reference to it. Returns `None` if the id is not found in the
+    /// container.
+    pub fn get_mut_from_id(&mut self, id: Id<T, I>) -> Option<&mut T> {
+        self.index_of_id(id)
+            .and_then(|index| self.data.get_mut(index))
+    }
+
+    /// Calls the given closure with a mutable reference to the value
+    /// associated with the given id and returns the result of the closure.
+    /// Returns `Error::IdNotFound` if the id is not found in the container.
+    pub fn modify<U>(&mut self, id: Id<T, I>, f: impl FnOnce(&mut T) -> U) -> Result<U, Error<R>> {
+        self.get_mut_from_id(id)
+            .map(f)
+            .ok_or(Error::IdNotFound(id.to_usize()))
+    }
+
+    /// Returns the entry for the given id, which is either occupied by a
+    /// value or vacant. The entry allows a value to be inspected, modified
+    /// or inserted with a single id lookup. Only ids that were handed out
+    /// before can be vacant, so `Error::IdNotFound` is returned for ids past
+    /// the end of the 'id' vector.
+    pub fn entry(&mut self, id: Id<T, I>) -> Result<Entry<'_, T, I, R>, Error<R>> {
+        if id.to_usize() >= self.id.len() {
+            return Err(Error::IdNotFound(id.to_usize()));
+        }
+        Ok(match self.index_of_id(id) {
+            Some(index) => Entry::Occupied(OccupiedEntry {
+                container: self,
+                id,
+                index,
+            }),
+            None => Entry::Vacant(VacantEntry {
+                container: self,
+                id,
+            }),
+        })
+    }
+
+    /// Returns mutable references to the values associated with each of the
+    /// given ids at once, in the same order as the ids. Returns `None` if any
+    /// of the ids is not found in the container, or if the same id is given
+    /// more than once.
+    pub fn get_many_mut<const N: usize>(&mut self, ids: [Id<T, I>; N]) -> Option<[&mut T; N]> {
+        let mut indices = [0; N];
+        for (index, id) in indices.iter_mut().zip(ids) {
+            *index = self.index_of_id(id)?;
+        }
+        self.data.get_disjoint_mut(indices).ok()
+    }
+
+    /// Returns the index in the 'data' vector of the value associated with
+    /// the given id, or `None` if the id is not found in the container. The
+    /// lookup goes through the 'data_index' vector and runs in constant time.
+    pub fn index_of_id(&self, id: Id<T, I>) -> Option<usize> {
+        self.index_of(id.to_usize())
+    }
+
+    /// Returns the index in the 'data' vector of the value associated with
+    /// the given bare id, or `None` if the id is not live.
+    fn index_of(&self, id: usize) -> Option<usize> {
+        self.data_index
+            .get(id)
+            .map(|index| index.to_usize())
+            .filter(|&index| {
+                self.live_ids()
+                    .get(index)
+                    .is_some_and(|live_id| live_id.to_usize() == id)
+            })
+    }
+
+    /// Checks if the given id is associated with a value in the container.
+    /// Returns `true` if the id is found, and `false` otherwise.
+    pub fn contains_id(&self, id: Id<T, I>) -> bool {
+        self.index_of_id(id).is_some()
     }
 
     /// Updates the value associated with the given id to the new data provided.
-    /// Returns `Ok(())` if the update is successful, or an error message if
-    /// the id is not found in the container or if the data index is out of bounds.
-    pub fn update(&mut self, id: usize, new_data: T) -> Result<(), &'static str> {
-        if let Some(index) = self.id.iter().position(|&x| x == id) {
-            if let Some(data_ref) = self.data.get_mut(index) {
-                *data_ref = new_data;
-                Ok(())
-            } else {
-                Err("Data index out of bounds")
-            }
+    /// Returns `Ok(())` if the update is successful, or an error if
+    /// the id is not found in the container.
+    pub fn update(&mut self, id: Id<T, I>, new_data: T) -> Result<(), Error<R>> {
+        if let Some(index) = self.index_of_id(id) {
+            self.data[index] = new_data;
+            Ok(())
         } else {
-            Err("ID not found in the container")
+            Err(Error::IdNotFound(id.to_usize()))
         }
     }
 
     /// Retrieves the id associated with the given index. Returns
-    /// `Ok(usize)' if the index is valid, or an error message if the index
+    /// `Ok(Id<T, I>)' if the index is valid, or an error if the index
     /// is out of bounds.
-    pub fn get_id_from_index(&self, index: usize) -> Result<usize, &'static str> {
-        self.id.get(index).copied().ok_or("Index out of bounds")
+    pub fn get_id_from_index(&self, index: usize) -> Result<Id<T, I>, Error<R>> {
+        self.live_ids()
+            .get(index)
+            .map(|&id| Id::new(id))
+            .ok_or(Error::IndexOutOfBounds(index))
     }
 
-    /// Retrieves the all ids on the same index as the given reference. Returns
-    /// Some vector of references if the reference is valid, or None if the
-    /// reference is not found in the container.
-    pub fn get_ids_from_reference(&self, reference: usize) -> Option<Vec<usize>> {
-        let mut ids = Vec::new();
-        for (i, &ref_value) in self.reference.iter().enumerate() {
-            if ref_value == reference
-                && let Some(id) = self.id.get(i)
+    /// Returns the live partition of the 'id' vector, i.e. the ids of the
+    /// values currently stored in the container, in the same order as 'data'.
+    fn live_ids(&self) -> &[I] {
+        &self.id[..self.data.len()]
+    }
+
+    /// Checks that the 'id' and 'data_index' vectors describe a consistent
+    /// container: every id maps back to its own position, there are no more
+    /// values than ids, and every id has a generation.
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.id.len() != self.data_index.len() {
+            return Err("id and data_index vectors have different lengths");
+        }
+        if self.data.len() > self.id.len() {
+            return Err("data vector is longer than the id vector");
+        }
+        if self.generation.len() < self.id.len() {
+            return Err("generation vector is shorter than the id vector");
+        }
+        if self.reference.len() != self.data.len() {
+            return Err("reference and data vectors have different lengths");
+        }
+        for (index, &id) in self.id.iter().enumerate() {
+            if self
+                .data_index
+                .get(id.to_usize())
+                .map(|index| index.to_usize())
+                != Some(index)
             {
-                ids.push(*id);
+                return Err("id and data_index vectors are inconsistent");
+            }
+        }
+        Ok(())
+    }
+
+    /// Retrieves the all ids on the same index as the given reference, in the
+    /// order they are stored in the 'data' vector. Returns Some vector of
+    /// ids if the reference is valid, or None if the reference is not found
+    /// in the container.
+    pub fn get_ids_from_reference<Q>(&self, reference: &Q) -> Option<Vec<Id<T, I>>>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut ids = self.reference_index.get(reference)?.clone();
+        ids.sort_unstable_by_key(|id| self.data_index[id.to_usize()]);
+        Some(ids.into_iter().map(Id::new).collect())
+    }
+
+    /// Returns the number of values associated with the given reference.
+    pub fn count_by_reference<Q>(&self, reference: &Q) -> usize
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.reference_index.get(reference).map_or(0, Vec::len)
+    }
+
+    /// Returns an iterator over the distinct references of the values in the
+    /// container, in ascending order.
+    pub fn references(&self) -> impl Iterator<Item = &R> {
+        self.reference_index.keys()
+    }
+
+    /// Returns an iterator over the ids associated with the given reference,
+    /// in no particular order. Unlike 'get_ids_from_reference', nothing is
+    /// allocated, and the iterator is empty if the reference is not found in
+    /// the container. Only the group of the reference in the
+    /// 'reference_index' map is visited, whatever the order of the container.
+    pub fn ids_by_reference<Q>(&self, reference: &Q) -> impl Iterator<Item = Id<T, I>>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.reference_index
+            .get(reference)
+            .into_iter()
+            .flatten()
+            .map(|&id| Id::new(id))
+    }
+
+    /// Returns an iterator over references to the values associated with the
+    /// given reference, in the same order as 'ids_by_reference'. Every id of
+    /// the group is resolved through the 'data_index' vector.
+    pub fn values_by_reference<Q>(&self, reference: &Q) -> impl Iterator<Item = &T>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.ids_by_reference(reference)
+            .map(|id| &self.data[self.data_index[id.to_usize()].to_usize()])
+    }
+
+    /// Returns an iterator over mutable references to the values associated
+    /// with the given reference, in the order they are stored in 'data'. Only
+    /// the span of 'data' covering the group is visited, which is exactly the
+    /// group while the container is sorted.
+    pub fn values_by_reference_mut<Q>(&mut self, reference: &Q) -> impl Iterator<Item = &mut T>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let span = self.reference_span(reference);
+        self.data[span.clone()]
+            .iter_mut()
+            .zip(&self.reference[span])
+            .filter(move |(_, other)| (*other).borrow() == reference)
+            .map(|(data, _)| data)
+    }
+
+    /// Returns the values associated with the given reference as a single
+    /// slice of the 'data' vector. This is only possible when the values are
+    /// stored next to each other, which is always the case after 'sort'.
+    /// Returns `None` if the reference is not found in the container or its
+    /// values are not contiguous.
+    pub fn slice_by_reference<Q>(&self, reference: &Q) -> Option<&[T]>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let span = self.contiguous_span(reference)?;
+        Some(&self.data[span])
+    }
+
+    /// Returns the values associated with the given reference as a single
+    /// mutable slice of the 'data' vector, in the same way as
+    /// 'slice_by_reference'.
+    pub fn slice_by_reference_mut<Q>(&mut self, reference: &Q) -> Option<&mut [T]>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let span = self.contiguous_span(reference)?;
+        Some(&mut self.data[span])
+    }
+
+    /// Returns the smallest range of indices that covers every value
+    /// associated with the given reference, or an empty range if the
+    /// reference is not found in the container.
+    fn reference_span<Q>(&self, reference: &Q) -> Range<usize>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let Some(ids) = self.reference_index.get(reference) else {
+            return 0..0;
+        };
+        let mut start = usize::MAX;
+        let mut end = 0;
+        for id in ids {
+            let index = self.data_index[id.to_usize()].to_usize();
+            start = start.min(index);
+            end = end.max(index + 1);
+        }
+        start..end
+    }
+
+    /// Returns the range of indices holding the values associated with the
+    /// given reference, if the reference is found in the container and its
+    /// values are stored next to each other.
+    fn contiguous_span<Q>(&self, reference: &Q) -> Option<Range<usize>>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let span = self.reference_span(reference);
+        (!span.is_empty() && span.len() == self.count_by_reference(reference)).then_some(span)
+    }
+
+    /// Records the given live id under the given reference in the
+    /// 'reference_index' map, and its position inside the group in the
+    /// 'group_index' vector.
+    fn index_reference(&mut self, id: I, reference: R) {
+        let ids = self.reference_index.entry(reference).or_default();
+        let position = I::from_usize(ids.len()).expect("This should never fail");
+        if self.group_index.len() < self.id.len() {
+            self.group_index.resize(self.id.len(), position);
+        }
+        self.group_index[id.to_usize()] = position;
+        ids.push(id);
+    }
+
+    /// Removes the given id from the group of the given reference in the
+    /// 'reference_index' map, dropping the group once it is empty. The last
+    /// id of the group takes the place of the removed one, so only its entry
+    /// in 'group_index' has to be updated. Nothing happens if the group has
+    /// already been taken out of the map.
+    fn unindex_reference(&mut self, id: I, reference: &R) {
+        if let Some(ids) = self.reference_index.get_mut(reference) {
+            let position = self.group_index[id.to_usize()];
+            ids.swap_remove(position.to_usize());
+            if let Some(&moved) = ids.get(position.to_usize()) {
+                self.group_index[moved.to_usize()] = position;
             }
+            if ids.is_empty() {
+                self.reference_index.remove(reference);
+            }
+        }
+    }
+
+    /// Rebuilds the 'reference_index' map and the 'group_index' vector from
+    /// the live partition of the 'id' and 'reference' vectors.
+    fn rebuild_reference_index(&mut self) {
+        self.reference_index.clear();
+        self.group_index.clear();
+        for index in 0..self.data.len() {
+            self.index_reference(self.id[index], self.reference[index].clone());
         }
-        if ids.is_empty() { None } else { Some(ids) }
     }
 
     /// Finds the value associated with the given id and swaps it with the
     /// last element in the container, then removes the last element.
-    /// Returns Ok(()) if the id is found and removed successfully, or an
-    /// error message if the id is not found in the container.
-    pub fn remove(&mut self, id: usize) -> Result<(), &'static str> {
-        if let Some(index) = self.id.iter().position(|&x| x == id) {
-            let last_index = self.data.len() - 1;
+    /// Returns Ok(()) if the id is found and removed successfully, or
+    /// `Error::IdNotFound` if the id is not found in the container.
+    pub fn remove(&mut self, id: Id<T, I>) -> Result<(), Error<R>> {
+        self.take(id)?;
+        Ok(())
+    }
 
-            self.swap(index, last_index)?;
+    /// Removes the value associated with the given id in the same way as
+    /// 'remove', but returns the removed value instead of dropping it.
+    /// Returns `Error::IdNotFound` if the id is not found in the container.
+    pub fn take(&mut self, id: Id<T, I>) -> Result<T, Error<R>> {
+        self.take_with_relocation(id).map(|(data, _)| data)
+    }
 
-            self.data.pop();
-            self.reference.pop();
+    /// Removes the value associated with the given id in the same way as
+    /// 'remove', and reports which element, if any, was moved into the
+    /// index of the removed one. This allows external mirrors of the 'data'
+    /// vector to be patched without scanning it. Returns
+    /// `Error::IdNotFound` if the id is not found in the container.
+    pub fn remove_with_relocation(&mut self, id: Id<T, I>) -> Result<Removal<T, I>, Error<R>> {
+        self.take_with_relocation(id).map(|(_, removal)| removal)
+    }
 
-            Ok(())
+    /// Swaps the value associated with the given id with the last element
+    /// in the container and pops it, returning the removed value together
+    /// with a description of the element that took its place.
+    fn take_with_relocation(&mut self, id: Id<T, I>) -> Result<(T, Removal<T, I>), Error<R>> {
+        let index = self
+            .index_of_id(id)
+            .ok_or(Error::IdNotFound(id.to_usize()))?;
+        Ok(self.take_at(index))
+    }
+
+    /// Removes the value at the given index of the live partition in the
+    /// same way as 'take_with_relocation'. Callers that already know the
+    /// index of a live value use this to skip the id lookup.
+    fn take_at(&mut self, index: usize) -> (T, Removal<T, I>) {
+        let id = self.id[index];
+        let last_index = self.data.len() - 1;
+
+        self.generation[id.to_usize()] = self.generation[id.to_usize()].wrapping_add(1);
+        self.swap(index, last_index);
+
+        let reference = self.reference.pop().expect("This should never fail");
+        self.unindex_reference(id, &reference);
+
+        let moved = if index != last_index {
+            self.sorted = false;
+            Some((Id::new(self.id[index]), last_index, index))
         } else {
-            Err("ID not found in the container")
+            None
+        };
+        let removal = Removal {
+            removed_index: index,
+            moved,
+        };
+
+        (self.data.pop().expect("This should never fail"), removal)
+    }
+
+    /// Replaces the value associated with the given id with the new data
+    /// provided and returns the previous value. Returns `Error::IdNotFound`
+    /// if the id is not found in the container.
+    pub fn replace(&mut self, id: Id<T, I>, new_data: T) -> Result<T, Error<R>> {
+        self.get_mut_from_id(id)
+            .map(|data| std::mem::replace(data, new_data))
+            .ok_or(Error::IdNotFound(id.to_usize()))
+    }
+
+    /// Associates the value of the given id with a new reference and returns
+    /// the previous reference. The id of the value stays the same. If the
+    /// container has been sorted by reference and not reordered since, the
+    /// value is moved to the end of its new reference group so the container
+    /// stays sorted. Returns `Error::IdNotFound` if the id is not found in
+    /// the container.
+    pub fn set_reference(&mut self, id: Id<T, I>, reference: R) -> Result<R, Error<R>> {
+        let index = self
+            .index_of_id(id)
+            .ok_or(Error::IdNotFound(id.to_usize()))?;
+        Ok(self.set_reference_at(index, reference))
+    }
+
+    /// Associates the value at the given index of the live partition with a
+    /// new reference in the same way as 'set_reference' and returns the
+    /// previous reference.
+    fn set_reference_at(&mut self, index: usize, reference: R) -> R {
+        let id = self.id[index];
+        let previous = std::mem::replace(&mut self.reference[index], reference.clone());
+        self.unindex_reference(id, &previous);
+        self.index_reference(id, reference.clone());
+
+        if self.sorted && reference > previous {
+            let end = index
+                + 1
+                + self.reference[index + 1..].partition_point(|other| *other <= reference);
+            self.rotate_left(index..end, 1);
+        } else if self.sorted && reference < previous {
+            let start = self.reference[..index].partition_point(|other| *other <= reference);
+            self.rotate_left(start..index + 1, index - start);
         }
+        previous
     }
 
-    pub fn remove_by_reference(&mut self, reference: usize) -> Result<(), &'static str> {
-        let mut indices_to_remove = Vec::new();
-        for (i, &ref_value) in self.reference.iter().enumerate() {
-            if ref_value == reference {
-                indices_to_remove.push(i);
+    /// Associates every value of the old reference with the new reference,
+    /// merging the two groups if the new reference is already in use. The
+    /// ids of the values stay the same. If the container has been sorted by
+    /// reference and not reordered since, the moved values are stored next
+    /// to each other, so only they are moved to the end of the new group
+    /// instead of sorting the whole container again. Returns
+    /// `Error::ReferenceNotFound` if the old reference is not found in the
+    /// container.
+    pub fn move_reference<Q>(&mut self, old: &Q, new: R) -> Result<(), Error<R>>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ToOwned<Owned = R> + ?Sized,
+    {
+        let ids = self
+            .reference_index
+            .remove(old)
+            .ok_or_else(|| Error::ReferenceNotFound(old.to_owned()))?;
+        let ordering = new.cmp(&self.reference[self.data_index[ids[0].to_usize()].to_usize()]);
+        for id in &ids {
+            self.reference[self.data_index[id.to_usize()].to_usize()] = new.clone();
+        }
+
+        if self.sorted {
+            let start = ids
+                .iter()
+                .map(|id| self.data_index[id.to_usize()].to_usize())
+                .min()
+                .expect("This should never fail");
+            let end = start + ids.len();
+            match ordering {
+                Ordering::Greater => {
+                    let target = end + self.reference[end..].partition_point(|other| *other <= new);
+                    self.rotate_left(start..target, ids.len());
+                }
+                Ordering::Less => {
+                    let target = self.reference[..start].partition_point(|other| *other <= new);
+                    self.rotate_left(target..end, start - target);
+                }
+                Ordering::Equal => {}
             }
         }
+        let group = self.reference_index.entry(new).or_default();
+        for id in ids {
+            self.group_index[id.to_usize()] =
+                I::from_usize(group.len()).expect("This should never fail");
+            group.push(id);
+        }
+        Ok(())
+    }
+
+    /// Rotates the values in the given range of indices to the left, so that
+    /// the first `mid` values move to the back of the range, and updates
+    /// 'data_index' for the moved ids.
+    fn rotate_left(&mut self, range: Range<usize>, mid: usize) {
+        self.data[range.clone()].rotate_left(mid);
+        self.id[range.clone()].rotate_left(mid);
+        self.reference[range.clone()].rotate_left(mid);
+        for index in range {
+            self.data_index[self.id[index].to_usize()] =
+                I::from_usize(index).expect("This should never fail");
+        }
+    }
 
-        if indices_to_remove.is_empty() {
-            return Err("Reference not found in the container");
+    /// Removes every value associated with the given reference. Returns
+    /// `Ok(())` if at least one value was removed, or
+    /// `Error::ReferenceNotFound` if the reference is not found in the
+    /// container.
+    pub fn remove_by_reference<Q>(&mut self, reference: &Q) -> Result<(), Error<R>>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ToOwned<Owned = R> + ?Sized,
+    {
+        if self.take_by_reference(reference).is_empty() {
+            Err(Error::ReferenceNotFound(reference.to_owned()))
+        } else {
+            Ok(())
         }
+    }
+
+    /// Removes every value associated with the given reference and returns
+    /// them paired with their ids, in the order they were stored in the
+    /// 'data' vector. The whole group is taken out of the 'reference_index'
+    /// map at once, so the values are removed without touching it again.
+    /// Returns an empty vector if the reference is not found in the
+    /// container.
+    pub fn take_by_reference<Q>(&mut self, reference: &Q) -> Vec<(Id<T, I>, T)>
+    where
+        R: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let Some(mut ids) = self.reference_index.remove(reference) else {
+            return Vec::new();
+        };
+        ids.sort_unstable_by_key(|id| Reverse(self.data_index[id.to_usize()]));
 
-        for index in indices_to_remove.into_iter().rev() {
-            self.remove(self.id[index])?;
+        let mut taken = Vec::with_capacity(ids.len());
+        for id in ids {
+            let (data, _) = self.take_at(self.data_index[id.to_usize()].to_usize());
+            taken.push((Id::new(id), data));
         }
+        taken.reverse();
 
-        Ok(())
+        taken
+    }
+
+    /// Returns the reference of the value associated with the given id, or
+    /// `None` if the id is not found in the container. Range queries yield
+    /// only ids, so this is how their references are looked up afterwards.
+    pub fn reference_of(&self, id: Id<T, I>) -> Option<&R> {
+        self.index_of_id(id).map(|index| &self.reference[index])
+    }
+
+    /// Returns an iterator over the ids of the values whose references fall
+    /// inside the given range, ordered by reference. The ids of values with
+    /// the same reference are yielded in no particular order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end, or if both
+    /// bounds are excluded and equal.
+    pub fn ids_in_reference_range(
+        &self,
+        range: impl RangeBounds<R>,
+    ) -> impl Iterator<Item = Id<T, I>> {
+        self.reference_index
+            .range(range)
+            .flat_map(|(_, ids)| ids.iter().map(|&id| Id::new(id)))
+    }
+
+    /// Removes every value whose reference falls inside the given range and
+    /// returns the number of removed values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end, or if both
+    /// bounds are excluded and equal.
+    pub fn remove_reference_range(&mut self, range: impl RangeBounds<R>) -> usize {
+        let ids: Vec<I> = self
+            .reference_index
+            .range(range)
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect();
+        for &id in &ids {
+            self.take_at(self.data_index[id.to_usize()].to_usize());
+        }
+        ids.len()
+    }
+
+    /// Returns the smallest reference of the values in the container, or
+    /// `None` if the container is empty.
+    pub fn min_reference(&self) -> Option<&R> {
+        self.reference_index.keys().next()
+    }
+
+    /// Returns the largest reference of the values in the container, or
+    /// `None` if the container is empty.
+    pub fn max_reference(&self) -> Option<&R> {
+        self.reference_index.keys().next_back()
+    }
+
+    /// Removes a value associated with the smallest reference in the
+    /// container and returns it with its id and reference. If several values
+    /// share the smallest reference, the one stored first in the 'data'
+    /// vector is removed, so a sorted container is drained in order. Returns
+    /// `None` if the container is empty.
+    pub fn pop_min_reference(&mut self) -> Option<(Id<T, I>, R, T)> {
+        let (reference, ids) = self.reference_index.first_key_value()?;
+        let reference = reference.clone();
+        let index = ids
+            .iter()
+            .map(|id| self.data_index[id.to_usize()].to_usize())
+            .min()?;
+        let id = Id::new(self.id[index]);
+        let (data, _) = self.take_at(index);
+        Some((id, reference, data))
     }
 
     /// Adds a new element to the container and returns a reference to its
@@ -128,19 +740,188 @@ impl<T> ReferenceContainer<T> {
     /// data. Otherwise, it pushes the new data and also updates the 'id'
     /// and 'data_index' vectors accordingly. The method ensures that the
     /// new element is properly indexed and can be retrieved using its id in
-    /// the future.
-    pub fn add(&mut self, data: T, reference: usize) -> usize {
+    /// the future. Returns `Error::CapacityExhausted` if a new id is needed
+    /// but does not fit into the id type of the container.
+    pub fn add(&mut self, data: T, reference: R) -> Result<Id<T, I>, Error<R>> {
+        let index = self.data.len();
+        if self.data.len() >= self.id.len() {
+            let new_id = I::from_usize(index).ok_or(Error::CapacityExhausted)?;
+            self.id.push(new_id);
+            self.data_index.push(new_id);
+            if self.generation.len() < self.id.len() {
+                self.generation.push(0);
+            }
+        }
+        self.data.push(data);
+        self.reference.push(reference.clone());
+        self.index_reference(self.id[index], reference);
+        self.sorted = false;
+        Ok(Id::new(self.id[index]))
+    }
+
+    /// Inserts the given data under the given free id and returns its index
+    /// in the 'data' vector. The requested id is swapped to the front of the
+    /// free partition so that pushing the data makes it live.
+    fn insert_at(&mut self, id: usize, data: T, reference: R) -> usize {
         let index = self.data.len();
-        if self.data.len() < self.id.len() {
-            self.data.push(data);
-            self.reference.push(reference);
+        let position = self.data_index[id];
+        let displaced_id = self.id[index];
+        self.id.swap(index, position.to_usize());
+        self.data_index[id] = I::from_usize(index).expect("This should never fail");
+        self.data_index[displaced_id.to_usize()] = position;
+
+        self.data.push(data);
+        self.reference.push(reference.clone());
+        self.index_reference(self.id[index], reference);
+        self.sorted = false;
+        index
+    }
+
+    /// Adds a new element to the container in the same way as 'add', but
+    /// returns a generational handle to the element instead of its bare id.
+    pub fn add_with_handle(&mut self, data: T, reference: R) -> Result<Handle<T, I>, Error<R>> {
+        let id = self.add(data, reference)?;
+        Ok(Handle::new(id, self.generation[id.to_usize()]))
+    }
+
+    /// Adds all elements of the given iterator with their references to the
+    /// container and returns their ids, in the same order as the elements.
+    /// The bookkeeping for all elements is done at once: free ids are
+    /// recycled first, and the new ids needed for the rest are appended in a
+    /// single pass. Returns `Error::CapacityExhausted`, without adding any
+    /// element, if the id type of the container cannot hold the ids needed
+    /// for all of them.
+    pub fn add_many(
+        &mut self,
+        data: impl IntoIterator<Item = (T, R)>,
+    ) -> Result<Vec<Id<T, I>>, Error<R>> {
+        let (data, references): (Vec<T>, Vec<R>) = data.into_iter().unzip();
+        let start = self.reserve_ids(data.len())?;
+        self.data.extend(data);
+        self.reference.extend(references);
+        for index in start..self.data.len() {
+            self.index_reference(self.id[index], self.reference[index].clone());
+        }
+        self.sorted = false;
+        Ok(self.id[start..self.data.len()]
+            .iter()
+            .map(|&id| Id::new(id))
+            .collect())
+    }
+
+    /// Adds all elements of the given iterator to the container with the same
+    /// reference, in the same way as 'add_many'.
+    pub fn add_many_with_reference(
+        &mut self,
+        data: impl IntoIterator<Item = T>,
+        reference: R,
+    ) -> Result<Vec<Id<T, I>>, Error<R>> {
+        self.add_many(data.into_iter().map(|data| (data, reference.clone())))
+    }
+
+    /// Makes sure there are at least `count` free ids after the live ones,
+    /// appending new ids to the 'id' and 'data_index' vectors as needed, and
+    /// returns the index at which the new elements will be stored. Returns
+    /// `Error::CapacityExhausted` if the new ids do not fit into the id type.
+    fn reserve_ids(&mut self, count: usize) -> Result<usize, Error<R>> {
+        let start = self.data.len();
+        let end = start + count;
+        if end > self.id.len() && I::from_usize(end - 1).is_none() {
+            return Err(Error::CapacityExhausted);
+        }
+        self.reserve(count);
+        for new_id in self.id.len()..end {
+            let new_id = I::from_usize(new_id).expect("This should never fail");
+            self.id.push(new_id);
+            self.data_index.push(new_id);
+        }
+        if self.generation.len() < self.id.len() {
+            self.generation.resize(self.id.len(), 0);
+        }
+        Ok(start)
+    }
+
+    /// Removes the values associated with all of the given ids in the same
+    /// way as 'remove'. Every id is attempted, and the errors for all ids that
+    /// could not be removed are returned together instead of stopping at the
+    /// first one.
+    pub fn remove_many(&mut self, ids: &[Id<T, I>]) -> Result<(), Vec<Error<R>>> {
+        let errors: Vec<Error<R>> = ids.iter().filter_map(|&id| self.remove(id).err()).collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Keeps only the elements for which the given predicate returns `true`,
+    /// removing all others in a single pass. The predicate receives the id
+    /// and reference of every element and a mutable reference to its value.
+    /// Removed elements are swap-removed in the same way as 'remove'.
+    pub fn retain(&mut self, mut f: impl FnMut(Id<T, I>, &R, &mut T) -> bool) {
+        self.drain_filter(|id, reference, data| !f(id, reference, data))
+            .for_each(drop);
+    }
+
+    /// Returns an iterator that removes the elements for which the given
+    /// predicate returns `true` and yields them paired with their ids. The
+    /// predicate receives the id and reference of every element and a
+    /// mutable reference to its value. Elements are only removed while the
+    /// iterator is advanced, so dropping it early leaves the remaining
+    /// elements in the container.
+    pub fn drain_filter<F>(&mut self, f: F) -> DrainFilter<'_, T, I, R, F>
+    where
+        F: FnMut(Id<T, I>, &R, &mut T) -> bool,
+    {
+        DrainFilter {
+            container: self,
+            index: 0,
+            predicate: f,
+        }
+    }
+
+    /// Returns a generational handle to the element associated with the given
+    /// id, or `None` if the id is not found in the container.
+    pub fn handle_of(&self, id: Id<T, I>) -> Option<Handle<T, I>> {
+        if self.contains_id(id) {
+            Some(Handle::new(id, self.generation[id.to_usize()]))
         } else {
-            self.data.push(data);
-            self.id.push(index);
-            self.data_index.push(index);
-            self.reference.push(reference);
+            None
+        }
+    }
+
+    /// Finds the value the given handle points to and returns a reference to
+    /// it. Returns `None` if the id of the handle is not found in the
+    /// container or if the handle is stale.
+    pub fn get_from_handle(&self, handle: Handle<T, I>) -> Option<&T> {
+        self.id_from_handle(handle)
+            .ok()
+            .and_then(|id| self.get_from_id(id))
+    }
+
+    /// Updates the value the given handle points to. Behaves like 'update',
+    /// but returns `Error::StaleHandle` if the handle is stale.
+    pub fn update_by_handle(&mut self, handle: Handle<T, I>, new_data: T) -> Result<(), Error<R>> {
+        let id = self.id_from_handle(handle)?;
+        self.update(id, new_data)
+    }
+
+    /// Removes the value the given handle points to. Behaves like 'remove',
+    /// but returns `Error::StaleHandle` if the handle is stale.
+    pub fn remove_by_handle(&mut self, handle: Handle<T, I>) -> Result<(), Error<R>> {
+        let id = self.id_from_handle(handle)?;
+        self.remove(id)
+    }
+
+    /// Resolves the given handle to its id. Returns an error if the
+    /// id of the handle was never handed out by the container, or if the
+    /// generation of the handle no longer matches the generation of the id.
+    fn id_from_handle(&self, handle: Handle<T, I>) -> Result<Id<T, I>, Error<R>> {
+        match self.generation.get(handle.id().to_usize()) {
+            Some(&generation) if generation == handle.generation() => Ok(handle.id()),
+            Some(_) => Err(Error::StaleHandle),
+            None => Err(Error::IdNotFound(handle.id().to_usize())),
         }
-        self.id.get(index).copied().expect("This should never fail")
     }
 
     /// Swaps the elements at the specified indices in the container. This
@@ -148,17 +929,161 @@ impl<T> ReferenceContainer<T> {
     /// corresponding elements in the 'data', 'id', and
     /// 'reference' vectors are swapped together. It also updates the
     /// 'data_index' vector to reflect the new positions of the swapped elements.
-    fn swap(&mut self, index_a: usize, index_b: usize) -> Result<(), &'static str> {
+    fn swap(&mut self, index_a: usize, index_b: usize) {
         self.data.swap(index_a, index_b);
         self.id.swap(index_a, index_b);
         self.reference.swap(index_a, index_b);
 
-        let data_index_a = self.get_id_from_index(index_a)?;
-        let data_index_b = self.get_id_from_index(index_b)?;
+        let data_index_a = self.id[index_a].to_usize();
+        let data_index_b = self.id[index_b].to_usize();
 
         self.data_index.swap(data_index_a, data_index_b);
+    }
 
-        Ok(())
+    /// Sorts the live elements in the container based on their reference
+    /// values, keeping the ids of all elements valid. The sort is stable, so
+    /// elements with the same reference keep their relative order. Free ids
+    /// are left untouched, and values are moved into place without being
+    /// cloned.
+    pub fn sort(&mut self) {
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        order.sort_by(|&a, &b| self.reference[a].cmp(&self.reference[b]));
+        self.permute(order);
+        self.sorted = true;
+    }
+
+    /// Sorts the live elements in the container based on their reference
+    /// values. Like 'sort', but the order of elements with the same reference
+    /// is not preserved.
+    pub fn sort_unstable(&mut self) {
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        order.sort_unstable_by(|&a, &b| self.reference[a].cmp(&self.reference[b]));
+        self.permute(order);
+        self.sorted = true;
+    }
+
+    /// Sorts the live elements in the container by their reference values and
+    /// then by their ids, so the order within each reference group does not
+    /// depend on the previous order of the elements.
+    pub fn sort_by_reference_then_id(&mut self) {
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        order.sort_unstable_by(|&a, &b| {
+            self.reference[a]
+                .cmp(&self.reference[b])
+                .then(self.id[a].cmp(&self.id[b]))
+        });
+        self.permute(order);
+        self.sorted = true;
+    }
+
+    /// Sorts the live elements in the container by their reference values and
+    /// then by their values using the given comparator. The sort is stable,
+    /// so elements that compare equal keep their relative order.
+    pub fn sort_by_reference_then_by(&mut self, mut compare: impl FnMut(&T, &T) -> Ordering) {
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        order.sort_by(|&a, &b| {
+            self.reference[a]
+                .cmp(&self.reference[b])
+                .then_with(|| compare(&self.data[a], &self.data[b]))
+        });
+        self.permute(order);
+        self.sorted = true;
+    }
+
+    /// Sorts the elements in the container with the given comparator, keeping
+    /// the ids of all elements valid. The sort is stable, and values are moved
+    /// into place without being cloned. If the comparator panics, the
+    /// container is left unchanged.
+    pub fn sort_by(&mut self, mut compare: impl FnMut(&T, &T) -> Ordering) {
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        order.sort_by(|&a, &b| compare(&self.data[a], &self.data[b]));
+        self.permute(order);
+        self.sorted = false;
+    }
+
+    /// Sorts the elements in the container by the key extracted with the
+    /// given function, keeping the ids of all elements valid. The sort is
+    /// stable, and values are moved into place without being cloned.
+    pub fn sort_by_key<K: Ord>(&mut self, mut f: impl FnMut(&T) -> K) {
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        order.sort_by_key(|&index| f(&self.data[index]));
+        self.permute(order);
+        self.sorted = false;
+    }
+
+    /// Sorts the elements in the container with the given comparator, keeping
+    /// the ids of all elements valid. Like 'sort_by', but the order of equal
+    /// elements is not preserved.
+    pub fn sort_unstable_by(&mut self, mut compare: impl FnMut(&T, &T) -> Ordering) {
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        order.sort_unstable_by(|&a, &b| compare(&self.data[a], &self.data[b]));
+        self.permute(order);
+        self.sorted = false;
+    }
+
+    /// Rearranges the live elements so that the element at index `order[i]`
+    /// ends up at index 'i', then rebuilds 'data_index' for the live ids.
+    /// The permutation is applied in place by following its cycles, so every
+    /// element is moved at most once and never cloned.
+    fn permute(&mut self, mut order: Vec<usize>) {
+        for start in 0..order.len() {
+            let mut current = start;
+            loop {
+                let next = order[current];
+                order[current] = current;
+                if next == start {
+                    break;
+                }
+                self.data.swap(current, next);
+                self.id.swap(current, next);
+                self.reference.swap(current, next);
+                current = next;
+            }
+        }
+
+        for (index, id) in self.id[..self.data.len()].iter().enumerate() {
+            self.data_index[id.to_usize()] = I::from_usize(index).expect("This should never fail");
+        }
+    }
+
+    /// Returns an iterator over references to the values in the container,
+    /// in the order they are stored in the 'data' vector.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    /// Returns an iterator over mutable references to the values in the
+    /// container, in the order they are stored in the 'data' vector.
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.data.iter_mut()
+    }
+
+    /// Returns an iterator over the ids of the values in the container, in
+    /// the same order as the values are stored in the 'data' vector.
+    pub fn ids(&self) -> impl Iterator<Item = Id<T, I>> {
+        self.live_ids().iter().map(|&id| Id::new(id))
+    }
+
+    /// Returns an iterator over references to the values in the container.
+    /// This is the same as 'iter', provided to mirror 'ids'.
+    pub fn values(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    /// Returns an iterator over the values in the container paired with
+    /// their ids, yielding `(id, &T)` in the order of the 'data' vector.
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (Id<T, I>, &T)> {
+        self.ids().zip(self.data.iter())
+    }
+
+    /// Returns an iterator over the values in the container paired with
+    /// their ids and references, yielding `(id, reference, &T)` in the order
+    /// of the 'data' vector.
+    pub fn iter_with_references(&self) -> impl Iterator<Item = (Id<T, I>, &R, &T)> {
+        self.ids()
+            .zip(self.reference.iter())
+            .zip(self.data.iter())
+            .map(|((id, reference), data)| (id, reference, data))
     }
 
     /// Returns the number of elements currently stored in the container by
@@ -174,86 +1099,474 @@ impl<T> ReferenceContainer<T> {
         self.data.is_empty()
     }
 
+    /// Returns the number of elements the container can hold without
+    /// reallocating the 'data' vector.
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    /// Reserves space for at least `additional` more elements. Free ids are
+    /// taken into account, so the id bookkeeping only grows by the number of
+    /// ids that cannot be recycled.
+    pub fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+        self.reference.reserve(additional);
+        let new_ids = (self.data.len() + additional).saturating_sub(self.id.len());
+        self.id.reserve(new_ids);
+        self.data_index.reserve(new_ids);
+        self.group_index
+            .reserve((self.id.len() + new_ids).saturating_sub(self.group_index.len()));
+        self.generation
+            .reserve((self.id.len() + new_ids).saturating_sub(self.generation.len()));
+    }
+
+    /// Shrinks the capacity of the internal vectors as much as possible. This
+    /// does not release free ids; use 'compact_ids' for that.
+    pub fn shrink_to_fit(&mut self) {
+        self.data.shrink_to_fit();
+        self.id.shrink_to_fit();
+        self.data_index.shrink_to_fit();
+        self.generation.shrink_to_fit();
+        self.reference.shrink_to_fit();
+        self.group_index.shrink_to_fit();
+    }
+
+    /// Renumbers the ids of the container so that the live ids become
+    /// `0..size()`, in the order of the 'data' vector, and releases all free
+    /// ids. Returns a remap table indexed by the bare value of every old id,
+    /// holding the new id of each live element and `None` for ids that were
+    /// free. The generations of all ids are increased, so handles created
+    /// before the compaction are rejected as stale.
+    pub fn compact_ids(&mut self) -> Vec<Option<Id<T, I>>> {
+        let mut remap = vec![None; self.id.len()];
+        for index in 0..self.data.len() {
+            let new_id = I::from_usize(index).expect("This should never fail");
+            remap[self.id[index].to_usize()] = Some(Id::new(new_id));
+            self.id[index] = new_id;
+            self.data_index[index] = new_id;
+        }
+        self.id.truncate(self.data.len());
+        self.data_index.truncate(self.data.len());
+        self.rebuild_reference_index();
+        for generation in self.generation.iter_mut() {
+            *generation = generation.wrapping_add(1);
+        }
+        remap
+    }
+
     /// Clears all elements from the container by clearing the 'data', 'id', and
     /// 'data_index' vectors. This effectively resets the container to an
     /// empty state, allowing it to be reused without any remaining data
-    /// from previous operations.
+    /// from previous operations. The generations of the ids are kept and
+    /// increased, so handles created before the clear are rejected as stale.
     pub fn clear(&mut self) {
+        for generation in self.generation.iter_mut() {
+            *generation = generation.wrapping_add(1);
+        }
         self.data.clear();
         self.id.clear();
         self.data_index.clear();
         self.reference.clear();
+        self.reference_index.clear();
+        self.group_index.clear();
+        self.sorted = false;
     }
 }
 
-impl<T: Clone> ReferenceContainer<T> {
-    /// Sorts the elements in the container based on their reference values. The
-    /// method should rearrange the elements in the 'data', 'id', 'data_index',
-    /// and 'reference' vectors to maintain the correct associations between
-    /// ids, data, and references after sorting. The sorting can be done using
-    /// any sorting algorithm, but it should ensure that the integrity of the
-    /// container is maintained and that the elements are correctly ordered
-    /// based on their reference values.
-    pub fn sort(&mut self) {
-        let mut combined: Vec<(usize, usize, T, usize)> = Vec::new();
-        for i in 0..self.id.len() {
-            if let (Some(id), Some(data), Some(reference)) =
-                (self.id.get(i), self.data.get(i), self.reference.get(i))
-            {
-                combined.push((*id, i, data.clone(), *reference));
-            }
+impl<'de, T, I, R> Deserialize<'de> for ReferenceContainer<T, I, R>
+where
+    T: Deserialize<'de>,
+    I: IdInt,
+    R: Deserialize<'de> + Ord + Hash + Clone,
+{
+    /// Deserializes the full state of the container, including the free ids
+    /// and their generations, so ids and handles stay valid across a round
+    /// trip. The container is validated before it is returned, and
+    /// inconsistent 'id' and 'data_index' vectors are rejected.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "ReferenceContainer")]
+        struct Fields<T, I, R> {
+            data_index: Vec<I>,
+            id: Vec<I>,
+            data: Vec<T>,
+            generation: Vec<u32>,
+            reference: Vec<R>,
         }
 
-        combined.sort_by_key(|k| k.3);
+        let fields = Fields::deserialize(deserializer)?;
+        let mut container = ReferenceContainer {
+            data_index: fields.data_index,
+            id: fields.id,
+            data: fields.data,
+            generation: fields.generation,
+            reference: fields.reference,
+            reference_index: BTreeMap::new(),
+            group_index: Vec::new(),
+            sorted: false,
+        };
+        container.validate().map_err(D::Error::custom)?;
+        container.rebuild_reference_index();
+        Ok(container)
+    }
+}
 
-        for (new_index, (id, _old_index, data, reference)) in combined.into_iter().enumerate() {
-            self.id[new_index] = id;
-            self.data[new_index] = data;
-            self.reference[new_index] = reference;
-            self.data_index[id] = new_index;
-        }
+impl<T: fmt::Debug, I: IdInt, R: fmt::Debug + Ord + Hash + Clone> fmt::Debug
+    for ReferenceContainer<T, I, R>
+{
+    /// Formats the container as a map from ids to `(reference, value)` pairs,
+    /// in the order the values are stored in the 'data' vector.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.iter_with_references()
+                    .map(|(id, reference, data)| (id.value(), (reference, data))),
+            )
+            .finish()
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl<T: PartialEq, I: IdInt, R: Ord + Hash + Clone> PartialEq for ReferenceContainer<T, I, R> {
+    /// Compares two containers by their id to value mappings. Two containers
+    /// are equal if they associate the same ids with equal values and equal
+    /// references, regardless of the order of the values in the 'data'
+    /// vector or of their free ids.
+    fn eq(&self, other: &Self) -> bool {
+        self.size() == other.size()
+            && self.iter_with_references().all(|(id, reference, data)| {
+                other.index_of_id(id).is_some_and(|index| {
+                    &other.reference[index] == reference && &other.data[index] == data
+                })
+            })
+    }
+}
 
-    /// Helper function to create a sample container for testing purposes.
-    fn setup_container() -> ReferenceContainer<String> {
-        ReferenceContainer {
-            data_index: vec![0, 1, 2],
-            id: vec![0, 1, 2],
-            data: vec!["a".to_string(), "b".to_string(), "c".to_string()],
-            reference: vec![0, 1, 1],
+impl<T: Eq, I: IdInt, R: Ord + Hash + Clone> Eq for ReferenceContainer<T, I, R> {}
+
+impl<T: Hash, I: IdInt, R: Ord + Hash + Clone> Hash for ReferenceContainer<T, I, R> {
+    /// Hashes the id to value mappings of the container in id order, so
+    /// that containers which compare equal also hash equally.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.size().hash(state);
+        for id in 0..self.data_index.len() {
+            if let Some(index) = self.index_of(id) {
+                id.hash(state);
+                self.reference[index].hash(state);
+                self.data[index].hash(state);
+            }
         }
     }
+}
 
-    fn setup_unsorted_container() -> ReferenceContainer<String> {
-        ReferenceContainer {
-            data_index: vec![0, 1, 2],
-            id: vec![0, 1, 2],
-            data: vec!["a".to_string(), "b".to_string(), "c".to_string()],
-            reference: vec![2, 0, 1],
-        }
+impl<T, I: IdInt, R: Ord + Hash + Clone> Index<Id<T, I>> for ReferenceContainer<T, I, R> {
+    type Output = T;
+
+    /// Returns a reference to the value associated with the given id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id is not found in the container.
+    fn index(&self, id: Id<T, I>) -> &T {
+        self.get_from_id(id).expect("ID not found in the container")
     }
+}
 
-    /// Tests the 'clone' method of the Container struct to ensure it creates a
-    /// new instance of the container with the same data, id, data_index, and
-    /// reference values as the original container. This test checks that the
-    /// cloned container is identical to the original container in terms of its
-    /// contents and structure, confirming that the cloning process is
-    /// functioning correctly. It verifies that all the fields of the cloned
-    /// container match those of the original container, ensuring that the clone
-    /// is a true copy of the original. This test is crucial for validating the
-    /// correctness of the Clone implementation for the Container struct.
-    #[test]
-    fn test_clone() {
-        let container = setup_container();
-        let cloned_container = container.clone();
+impl<T, I: IdInt, R: Ord + Hash + Clone> IndexMut<Id<T, I>> for ReferenceContainer<T, I, R> {
+    /// Returns a mutable reference to the value associated with the given id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id is not found in the container.
+    fn index_mut(&mut self, id: Id<T, I>) -> &mut T {
+        self.get_mut_from_id(id)
+            .expect("ID not found in the container")
+    }
+}
+
+impl<T, I: IdInt, R: Ord + Hash + Clone> Index<Handle<T, I>> for ReferenceContainer<T, I, R> {
+    type Output = T;
+
+    /// Returns a reference to the value the given handle points to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle is stale or its id is not found in the container.
+    fn index(&self, handle: Handle<T, I>) -> &T {
+        self.get_from_handle(handle)
+            .expect("Handle is stale or not found in the container")
+    }
+}
+
+impl<T, I: IdInt, R: Ord + Hash + Clone> IndexMut<Handle<T, I>> for ReferenceContainer<T, I, R> {
+    /// Returns a mutable reference to the value the given handle points to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle is stale or its id is not found in the container.
+    fn index_mut(&mut self, handle: Handle<T, I>) -> &mut T {
+        self.id_from_handle(handle)
+            .ok()
+            .and_then(|id| self.get_mut_from_id(id))
+            .expect("Handle is stale or not found in the container")
+    }
+}
+
+impl<T, I: IdInt, R: Ord + Hash + Clone> FromIterator<(T, R)> for ReferenceContainer<T, I, R> {
+    fn from_iter<Iter: IntoIterator<Item = (T, R)>>(iter: Iter) -> Self {
+        let mut container = ReferenceContainer::with_id_type();
+        container.extend(iter);
+        container
+    }
+}
+
+impl<T, I: IdInt, R: Ord + Hash + Clone> Extend<(T, R)> for ReferenceContainer<T, I, R> {
+    fn extend<Iter: IntoIterator<Item = (T, R)>>(&mut self, iter: Iter) {
+        if self.add_many(iter).is_err() {
+            panic!("Container capacity exhausted");
+        }
+    }
+}
+
+impl<T, I, R> IntoIterator for ReferenceContainer<T, I, R> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    /// Consumes the container and returns an iterator over its values, in
+    /// the order they are stored in the 'data' vector.
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, T, I: IdInt, R: Ord + Hash + Clone> IntoIterator for &'a ReferenceContainer<T, I, R> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, I: IdInt, R: Ord + Hash + Clone> IntoIterator for &'a mut ReferenceContainer<T, I, R> {
+    type Item = &'a mut T;
+    type IntoIter = std::slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// An iterator that removes the elements matching a predicate from a
+/// container and yields them with their ids. Returned by
+/// 'ReferenceContainer::drain_filter'.
+pub struct DrainFilter<'a, T, I, R, F> {
+    container: &'a mut ReferenceContainer<T, I, R>,
+    index: usize,
+    predicate: F,
+}
+
+impl<T, I: IdInt, R: Ord + Hash + Clone, F> Iterator for DrainFilter<'_, T, I, R, F>
+where
+    F: FnMut(Id<T, I>, &R, &mut T) -> bool,
+{
+    type Item = (Id<T, I>, T);
+
+    /// Advances through the 'data' vector until an element matches the
+    /// predicate and removes it. Since removal swaps the last element into
+    /// the current index, the index is only advanced past kept elements.
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.container.data.len() {
+            let id = Id::new(self.container.id[self.index]);
+            let reference = &self.container.reference[self.index];
+            if (self.predicate)(id, reference, &mut self.container.data[self.index]) {
+                let (data, _) = self.container.take_at(self.index);
+                return Some((id, data));
+            }
+            self.index += 1;
+        }
+        None
+    }
+}
+
+/// A view into a single id of a container, which is either occupied by a
+/// value or vacant. Returned by 'ReferenceContainer::entry'.
+pub enum Entry<'a, T, I = usize, R = usize> {
+    Occupied(OccupiedEntry<'a, T, I, R>),
+    Vacant(VacantEntry<'a, T, I, R>),
+}
+
+/// An entry for an id that is associated with a value in the container.
+pub struct OccupiedEntry<'a, T, I = usize, R = usize> {
+    container: &'a mut ReferenceContainer<T, I, R>,
+    id: Id<T, I>,
+    index: usize,
+}
+
+/// An entry for an id that is not associated with a value in the container.
+pub struct VacantEntry<'a, T, I = usize, R = usize> {
+    container: &'a mut ReferenceContainer<T, I, R>,
+    id: Id<T, I>,
+}
+
+impl<'a, T, I: IdInt, R: Ord + Hash + Clone> Entry<'a, T, I, R> {
+    /// Returns the id of the entry.
+    pub fn id(&self) -> Id<T, I> {
+        match self {
+            Entry::Occupied(entry) => entry.id(),
+            Entry::Vacant(entry) => entry.id(),
+        }
+    }
+
+    /// Returns a mutable reference to the value of the entry, inserting the
+    /// given default with the given reference first if the entry is vacant.
+    pub fn or_insert(self, default: T, reference: R) -> &'a mut T {
+        self.or_insert_with(|| default, reference)
+    }
+
+    /// Returns a mutable reference to the value of the entry, inserting the
+    /// result of the given closure with the given reference first if the
+    /// entry is vacant.
+    pub fn or_insert_with(self, f: impl FnOnce() -> T, reference: R) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(f(), reference),
+        }
+    }
+
+    /// Calls the given closure with a mutable reference to the value if the
+    /// entry is occupied, and returns the entry for further chaining.
+    pub fn and_modify(mut self, f: impl FnOnce(&mut T)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, T, I: IdInt, R: Ord + Hash + Clone> OccupiedEntry<'a, T, I, R> {
+    /// Returns the id of the entry.
+    pub fn id(&self) -> Id<T, I> {
+        self.id
+    }
+
+    /// Returns a reference to the value of the entry.
+    pub fn get(&self) -> &T {
+        &self.container.data[self.index]
+    }
+
+    /// Returns a mutable reference to the value of the entry.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.container.data[self.index]
+    }
+
+    /// Converts the entry into a mutable reference to its value, bound to
+    /// the lifetime of the container.
+    pub fn into_mut(self) -> &'a mut T {
+        &mut self.container.data[self.index]
+    }
+
+    /// Replaces the value of the entry and returns the previous value.
+    pub fn insert(&mut self, data: T) -> T {
+        std::mem::replace(self.get_mut(), data)
+    }
+
+    /// Returns the reference associated with the value of the entry.
+    pub fn reference(&self) -> &R {
+        &self.container.reference[self.index]
+    }
+
+    /// Associates the value of the entry with the given reference in the
+    /// same way as 'ReferenceContainer::set_reference' and returns the
+    /// previous reference.
+    pub fn set_reference(&mut self, reference: R) -> R {
+        let previous = self.container.set_reference_at(self.index, reference);
+        self.index = self
+            .container
+            .index_of_id(self.id)
+            .expect("This should never fail");
+        previous
+    }
+
+    /// Removes the value of the entry from the container in the same way as
+    /// 'ReferenceContainer::take' and returns it.
+    pub fn remove(self) -> T {
+        self.container.take_at(self.index).0
+    }
+}
+
+impl<'a, T, I: IdInt, R: Ord + Hash + Clone> VacantEntry<'a, T, I, R> {
+    /// Returns the id of the entry.
+    pub fn id(&self) -> Id<T, I> {
+        self.id
+    }
+
+    /// Inserts the given value with the given reference under the id of the
+    /// entry and returns a mutable reference to it.
+    pub fn insert(self, data: T, reference: R) -> &'a mut T {
+        let index = self
+            .container
+            .insert_at(self.id.to_usize(), data, reference);
+        &mut self.container.data[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Helper function to create a typed id for the containers under test.
+    fn id(value: usize) -> Id<String> {
+        Id::new(value)
+    }
+
+    /// Helper function to create a sample container for testing purposes.
+    fn setup_container() -> ReferenceContainer<String> {
+        let mut container = ReferenceContainer {
+            data_index: vec![0, 1, 2],
+            id: vec![0, 1, 2],
+            data: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            generation: vec![0, 0, 0],
+            reference: vec![0, 1, 1],
+            reference_index: BTreeMap::new(),
+            group_index: Vec::new(),
+            sorted: false,
+        };
+        container.rebuild_reference_index();
+        container
+    }
+
+    fn setup_unsorted_container() -> ReferenceContainer<String> {
+        let mut container = ReferenceContainer {
+            data_index: vec![0, 1, 2],
+            id: vec![0, 1, 2],
+            data: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            generation: vec![0, 0, 0],
+            reference: vec![2, 0, 1],
+            reference_index: BTreeMap::new(),
+            group_index: Vec::new(),
+            sorted: false,
+        };
+        container.rebuild_reference_index();
+        container
+    }
+
+    /// Tests the 'clone' method of the Container struct to ensure it creates a
+    /// new instance of the container with the same data, id, data_index, and
+    /// reference values as the original container. This test checks that the
+    /// cloned container is identical to the original container in terms of its
+    /// contents and structure, confirming that the cloning process is
+    /// functioning correctly. It verifies that all the fields of the cloned
+    /// container match those of the original container, ensuring that the clone
+    /// is a true copy of the original. This test is crucial for validating the
+    /// correctness of the Clone implementation for the Container struct.
+    #[test]
+    fn test_clone() {
+        let container = setup_container();
+        let cloned_container = container.clone();
         assert_eq!(container.data, cloned_container.data);
         assert_eq!(container.id, cloned_container.id);
         assert_eq!(container.data_index, cloned_container.data_index);
+        assert_eq!(container.generation, cloned_container.generation);
         assert_eq!(container.reference, cloned_container.reference);
     }
 
@@ -263,29 +1576,43 @@ mod tests {
     #[test]
     fn test_get() {
         let container = setup_container();
-        assert_eq!(container.get_from_id(0), Some(&"a".to_string()));
-        assert_eq!(container.get_from_id(1), Some(&"b".to_string()));
-        assert_eq!(container.get_from_id(2), Some(&"c".to_string()));
-        assert_eq!(container.get_from_id(3), None);
+        assert_eq!(container.get_from_id(id(0)), Some(&"a".to_string()));
+        assert_eq!(container.get_from_id(id(1)), Some(&"b".to_string()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"c".to_string()));
+        assert_eq!(container.get_from_id(id(3)), None);
+    }
+
+    /// Tests the 'index_of_id' and 'contains_id' methods of the Container
+    /// struct to ensure ids are resolved to their current index and that
+    /// removed or unknown ids are reported as missing.
+    #[test]
+    fn test_id_lookup() {
+        let mut container = setup_container();
+        assert_eq!(container.index_of_id(id(1)), Some(1));
+        assert!(container.contains_id(id(2)));
+        container.remove(id(0)).unwrap();
+        assert_eq!(container.index_of_id(id(2)), Some(0));
+        assert_eq!(container.index_of_id(id(0)), None);
+        assert!(!container.contains_id(id(0)));
+        assert!(!container.contains_id(id(3)));
     }
 
     /// Tests the 'update' method of the Container struct to ensure it
     /// correctly updates values based on their associated ids and returns
-    /// appropriate error messages when the id is not found or when the data
-    /// index is out of bounds.
+    /// appropriate errors when the id is not found or has been removed.
     #[test]
     fn test_update() {
         let mut container = setup_container();
-        assert_eq!(container.update(1, "updated".to_string()), Ok(()));
-        assert_eq!(container.get_from_id(1), Some(&"updated".to_string()));
+        assert_eq!(container.update(id(1), "updated".to_string()), Ok(()));
+        assert_eq!(container.get_from_id(id(1)), Some(&"updated".to_string()));
         assert_eq!(
-            container.update(3, "new".to_string()),
-            Err("ID not found in the container")
+            container.update(id(3), "new".to_string()),
+            Err(Error::IdNotFound(3))
         );
-        container.remove(2).unwrap();
+        container.remove(id(2)).unwrap();
         assert_eq!(
-            container.update(2, "new".to_string()),
-            Err("Data index out of bounds")
+            container.update(id(2), "new".to_string()),
+            Err(Error::IdNotFound(2))
         );
     }
 
@@ -295,11 +1622,17 @@ mod tests {
     #[test]
     fn test_reference_methods() {
         let container = setup_container();
-        assert_eq!(container.get_id_from_index(1), Ok(1));
-        assert_eq!(container.get_id_from_index(3), Err("Index out of bounds"));
-        assert_eq!(container.get_ids_from_reference(1), Some(vec![1, 2]));
-        assert_eq!(container.get_ids_from_reference(0), Some(vec![0]));
-        assert_eq!(container.get_ids_from_reference(2), None);
+        assert_eq!(container.get_id_from_index(1), Ok(id(1)));
+        assert_eq!(
+            container.get_id_from_index(3),
+            Err(Error::IndexOutOfBounds(3))
+        );
+        assert_eq!(
+            container.get_ids_from_reference(&1),
+            Some(vec![id(1), id(2)])
+        );
+        assert_eq!(container.get_ids_from_reference(&0), Some(vec![id(0)]));
+        assert_eq!(container.get_ids_from_reference(&2), None);
     }
 
     /// Tests the 'remove' method of the Container struct to ensure it
@@ -308,9 +1641,24 @@ mod tests {
     #[test]
     fn test_remove() {
         let mut container = setup_container();
-        assert_eq!(container.remove(2), Ok(()));
-        assert_eq!(container.get_from_id(2), None);
-        assert_eq!(container.remove(3), Err("ID not found in the container"));
+        assert_eq!(container.remove(id(2)), Ok(()));
+        assert_eq!(container.get_from_id(id(2)), None);
+        assert_eq!(container.remove(id(3)), Err(Error::IdNotFound(3)));
+    }
+
+    /// Tests the 'remove_by_reference' method of the Container struct to
+    /// ensure it removes every element sharing the given reference and
+    /// returns an error for references that are not present in the container.
+    #[test]
+    fn test_remove_by_reference() {
+        let mut container = setup_container();
+        assert_eq!(container.remove_by_reference(&1), Ok(()));
+        assert_eq!(container.size(), 1);
+        assert_eq!(container.get_from_id(id(0)), Some(&"a".to_string()));
+        assert_eq!(
+            container.remove_by_reference(&1),
+            Err(Error::ReferenceNotFound(1))
+        );
     }
 
     /// Tests the 'add' method of the Container struct to ensure it
@@ -320,10 +1668,10 @@ mod tests {
     #[test]
     fn test_add() {
         let mut container = setup_container();
-        let new_id = container.add("d".to_string(), 5);
+        let new_id = container.add("d".to_string(), 5).unwrap();
         assert_eq!(container.get_from_id(new_id), Some(&"d".to_string()));
-        container.remove(1).unwrap();
-        let new_id2 = container.add("e".to_string(), 6);
+        container.remove(id(1)).unwrap();
+        let new_id2 = container.add("e".to_string(), 6).unwrap();
         assert_eq!(container.get_from_id(new_id2), Some(&"e".to_string()));
     }
 
@@ -350,12 +1698,891 @@ mod tests {
 
         container.sort();
 
-        assert_eq!(container.get_from_id(1), Some(&"b".to_string()));
-        assert_eq!(container.get_from_id(2), Some(&"c".to_string()));
-        assert_eq!(container.get_from_id(0), Some(&"a".to_string()));
+        assert_eq!(container.get_from_id(id(1)), Some(&"b".to_string()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"c".to_string()));
+        assert_eq!(container.get_from_id(id(0)), Some(&"a".to_string()));
+
+        assert_eq!(container.get_ids_from_reference(&0), Some(vec![id(1)]));
+        assert_eq!(container.get_ids_from_reference(&1), Some(vec![id(2)]));
+        assert_eq!(container.get_ids_from_reference(&2), Some(vec![id(0)]));
+    }
+
+    /// Tests the handle related methods of the Container struct to ensure
+    /// handles resolve to their element while it is alive, and that handles
+    /// kept from before a removal are rejected once the id has been reused.
+    #[test]
+    fn test_handles() {
+        let mut container = setup_container();
+        let handle = container.add_with_handle("d".to_string(), 0).unwrap();
+        assert_eq!(container.get_from_handle(handle), Some(&"d".to_string()));
+        assert_eq!(container.handle_of(handle.id()), Some(handle));
+        assert_eq!(container.remove_by_handle(handle), Ok(()));
+        assert_eq!(container.handle_of(handle.id()), None);
+
+        let reused = container.add("e".to_string(), 0).unwrap();
+        assert_eq!(reused, handle.id());
+        assert_eq!(container.get_from_handle(handle), None);
+        assert_eq!(
+            container.update_by_handle(handle, "f".to_string()),
+            Err(Error::StaleHandle)
+        );
+        assert_eq!(container.remove_by_handle(handle), Err(Error::StaleHandle));
+        assert_eq!(container.get_from_id(reused), Some(&"e".to_string()));
+
+        let current = container.handle_of(reused).unwrap();
+        assert_eq!(current.generation(), handle.generation() + 1);
+        container.clear();
+        container.add("d".to_string(), 0).unwrap();
+        assert_eq!(
+            container.get_from_handle(container.handle_of(id(0)).unwrap()),
+            Some(&"d".to_string())
+        );
+        assert_eq!(container.get_from_handle(Handle::new(id(0), 0)), None);
+    }
+
+    /// Tests that ids which have been removed are reliably rejected by every
+    /// operation, even after they have been recycled by a later 'add', and
+    /// that removing an id twice never touches the remaining live elements.
+    #[test]
+    fn test_freed_ids() {
+        let mut container = setup_container();
+        assert_eq!(container.remove(id(0)), Ok(()));
+        assert_eq!(container.remove(id(0)), Err(Error::IdNotFound(0)));
+        assert_eq!(container.size(), 2);
+        assert_eq!(container.get_from_id(id(1)), Some(&"b".to_string()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"c".to_string()));
+        assert_eq!(
+            container.get_id_from_index(2),
+            Err(Error::IndexOutOfBounds(2))
+        );
+
+        assert_eq!(container.remove(id(1)), Ok(()));
+        assert_eq!(container.remove(id(0)), Err(Error::IdNotFound(0)));
+        assert_eq!(container.remove(id(1)), Err(Error::IdNotFound(1)));
+        assert_eq!(container.get_from_id(id(0)), None);
+        assert_eq!(container.get_from_id(id(2)), Some(&"c".to_string()));
+
+        let readded = container.add("d".to_string(), 0).unwrap();
+        assert_eq!(readded, id(1));
+        assert_eq!(container.remove(id(0)), Err(Error::IdNotFound(0)));
+        assert_eq!(container.remove(readded), Ok(()));
+        assert_eq!(
+            container.remove(readded),
+            Err(Error::IdNotFound(readded.value()))
+        );
+        assert_eq!(
+            container.update(readded, "e".to_string()),
+            Err(Error::IdNotFound(readded.value()))
+        );
+        assert_eq!(container.size(), 1);
+        assert_eq!(container.get_from_id(id(2)), Some(&"c".to_string()));
+    }
+
+    /// Tests the iterator methods and 'IntoIterator' implementations of the
+    /// Container struct to ensure they walk the values in dense order and
+    /// pair them with the correct ids, skipping removed elements.
+    #[test]
+    fn test_iterators() {
+        let mut container = setup_container();
+        container.remove(id(0)).unwrap();
+        container.add("d".to_string(), 4).unwrap();
+
+        for value in container.iter_mut() {
+            value.push('!');
+        }
+        assert_eq!(
+            container.ids().collect::<Vec<_>>(),
+            vec![id(2), id(1), id(0)]
+        );
+        assert_eq!(
+            container.values().cloned().collect::<Vec<_>>(),
+            vec!["c!", "b!", "d!"]
+        );
+        assert_eq!(
+            container.iter_with_ids().collect::<Vec<_>>(),
+            vec![
+                (id(2), &"c!".to_string()),
+                (id(1), &"b!".to_string()),
+                (id(0), &"d!".to_string())
+            ]
+        );
+        assert_eq!(
+            container.iter_with_references().collect::<Vec<_>>(),
+            vec![
+                (id(2), &1, &"c!".to_string()),
+                (id(1), &1, &"b!".to_string()),
+                (id(0), &4, &"d!".to_string())
+            ]
+        );
+
+        for value in &mut container {
+            value.pop();
+        }
+        assert_eq!((&container).into_iter().count(), 3);
+        assert_eq!(container.iter().next(), Some(&"c".to_string()));
+        assert_eq!(
+            container.into_iter().collect::<Vec<_>>(),
+            vec!["c", "b", "d"]
+        );
+    }
+
+    /// Tests the mutable access methods of the Container struct to ensure
+    /// values can be changed in place by index and by id, and that
+    /// 'get_many_mut' rejects missing and duplicated ids.
+    #[test]
+    fn test_mutable_access() {
+        let mut container = setup_container();
+        container.get_mut(0).unwrap().push('!');
+        assert_eq!(container.get_from_id(id(0)), Some(&"a!".to_string()));
+        assert_eq!(container.get_mut(3), None);
+
+        container.get_mut_from_id(id(1)).unwrap().push('?');
+        assert_eq!(container.get_from_id(id(1)), Some(&"b?".to_string()));
+        assert_eq!(container.get_mut_from_id(id(3)), None);
+
+        assert_eq!(container.modify(id(2), |value| value.len()), Ok(1));
+        assert_eq!(container.modify(id(2), |value| value.push('#')), Ok(()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"c#".to_string()));
+        assert_eq!(container.modify(id(3), |_| ()), Err(Error::IdNotFound(3)));
+
+        let [a, c] = container.get_many_mut([id(0), id(2)]).unwrap();
+        std::mem::swap(a, c);
+        assert_eq!(container.get_from_id(id(0)), Some(&"c#".to_string()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"a!".to_string()));
+        assert!(container.get_many_mut([id(0), id(0)]).is_none());
+        assert!(container.get_many_mut([id(0), id(3)]).is_none());
+        container.remove(id(1)).unwrap();
+        assert!(container.get_many_mut([id(1), id(2)]).is_none());
+    }
+
+    /// Tests the 'take' and 'replace' methods of the Container struct to
+    /// ensure they hand back the removed or replaced value and keep the
+    /// swap-remove behavior of 'remove'.
+    #[test]
+    fn test_take_and_replace() {
+        let mut container = setup_container();
+        assert_eq!(
+            container.replace(id(1), "x".to_string()),
+            Ok("b".to_string())
+        );
+        assert_eq!(container.get_from_id(id(1)), Some(&"x".to_string()));
+        assert_eq!(
+            container.replace(id(3), "y".to_string()),
+            Err(Error::IdNotFound(3))
+        );
+
+        assert_eq!(container.take(id(0)), Ok("a".to_string()));
+        assert_eq!(container.take(id(0)), Err(Error::IdNotFound(0)));
+        assert_eq!(container.index_of_id(id(2)), Some(0));
+        assert_eq!(container.size(), 2);
+    }
+
+    /// Tests the 'take_by_reference' method of the Container struct to
+    /// ensure it returns every removed value with its id in dense order, and
+    /// nothing for references that are not present in the container.
+    #[test]
+    fn test_take_by_reference() {
+        let mut container = setup_container();
+        container.add("d".to_string(), 0).unwrap();
+        assert_eq!(
+            container.take_by_reference(&0),
+            vec![(id(0), "a".to_string()), (id(3), "d".to_string())]
+        );
+        assert_eq!(container.take_by_reference(&0), vec![]);
+        assert_eq!(container.size(), 2);
+        assert_eq!(container.get_from_id(id(1)), Some(&"b".to_string()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"c".to_string()));
+    }
+
+    /// Tests the removal of values from a large group of the Container
+    /// struct to ensure single removals keep the group consistent, and that
+    /// the rest of the group is removed at once, without scanning the group
+    /// for every removed value.
+    #[test]
+    fn test_remove_large_reference_group() {
+        let mut container = ReferenceContainer::new();
+        let ids = container
+            .add_many_with_reference((0..100_000).map(|value| value.to_string()), 1)
+            .unwrap();
+        container.add("x".to_string(), 2).unwrap();
+
+        for &id in ids.iter().step_by(2) {
+            container.remove(id).unwrap();
+        }
+        assert_eq!(container.count_by_reference(&1), 50_000);
+        assert_eq!(container.ids_by_reference(&1).count(), 50_000);
+
+        let taken = container.take_by_reference(&1);
+        assert_eq!(taken.len(), 50_000);
+        assert!(taken.iter().all(|(id, _)| id.value() % 2 == 1));
+        assert_eq!(container.count_by_reference(&1), 0);
+        assert_eq!(container.size(), 1);
+        assert_eq!(
+            container.get_ids_from_reference(&2),
+            Some(vec![id(100_000)])
+        );
+    }
+
+    /// Tests the 'remove_with_relocation' method of the Container struct to
+    /// ensure it reports the element that was swapped into the hole left by
+    /// the removed one, and nothing when the last element is removed.
+    #[test]
+    fn test_remove_with_relocation() {
+        let mut container = setup_container();
+        container.add("d".to_string(), 0).unwrap();
+        assert_eq!(
+            container.remove_with_relocation(id(1)),
+            Ok(Removal {
+                removed_index: 1,
+                moved: Some((id(3), 3, 1)),
+            })
+        );
+        assert_eq!(container.index_of_id(id(3)), Some(1));
+        assert_eq!(
+            container.remove_with_relocation(id(2)),
+            Ok(Removal {
+                removed_index: 2,
+                moved: None,
+            })
+        );
+        assert_eq!(
+            container.remove_with_relocation(id(2)),
+            Err(Error::IdNotFound(2))
+        );
+        assert_eq!(container.ids().collect::<Vec<_>>(), vec![id(0), id(3)]);
+    }
+
+    /// Tests the serde implementations of the Container struct to ensure a
+    /// round trip preserves live ids, free ids and generations, so handles
+    /// created before serialization stay valid, and that inconsistent input
+    /// is rejected.
+    #[test]
+    fn test_serde() {
+        let mut container = setup_container();
+        let handle = container.add_with_handle("d".to_string(), 3).unwrap();
+        container.remove(id(1)).unwrap();
+
+        let json = serde_json::to_string(&container).unwrap();
+        let mut restored: ReferenceContainer<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.data, container.data);
+        assert_eq!(restored.id, container.id);
+        assert_eq!(restored.data_index, container.data_index);
+        assert_eq!(restored.generation, container.generation);
+        assert_eq!(restored.reference, container.reference);
+        assert_eq!(restored.get_from_handle(handle), Some(&"d".to_string()));
+        assert_eq!(restored.add("e".to_string(), 3).unwrap(), id(1));
+        assert_eq!(restored.get_from_id(id(1)), Some(&"e".to_string()));
+
+        let handle_json = serde_json::to_string(&handle).unwrap();
+        let restored_handle: Handle<String> = serde_json::from_str(&handle_json).unwrap();
+        assert_eq!(restored_handle, handle);
+
+        let inconsistent = r#"{"data_index":[1,0],"id":[0,1],"data":["a","b"],"generation":[0,0],"reference":[0,0]}"#;
+        assert!(serde_json::from_str::<ReferenceContainer<String>>(inconsistent).is_err());
+        let out_of_range = r#"{"data_index":[0,1],"id":[0,5],"data":["a","b"],"generation":[0,0],"reference":[0,0]}"#;
+        assert!(serde_json::from_str::<ReferenceContainer<String>>(out_of_range).is_err());
+        let mismatched_references =
+            r#"{"data_index":[0],"id":[0],"data":["a"],"generation":[0],"reference":[]}"#;
+        assert!(serde_json::from_str::<ReferenceContainer<String>>(mismatched_references).is_err());
+    }
+
+    /// Tests the entry API of the Container struct to ensure occupied
+    /// entries give access to their value, and that vacant entries insert
+    /// under the requested id, while ids that were never handed out are
+    /// rejected.
+    #[test]
+    fn test_entry() {
+        let mut container = setup_container();
+        container
+            .entry(id(0))
+            .unwrap()
+            .and_modify(|value| value.push('!'));
+        assert_eq!(container.get_from_id(id(0)), Some(&"a!".to_string()));
+        assert_eq!(
+            container
+                .entry(id(1))
+                .unwrap()
+                .or_insert("x".to_string(), 2),
+            "b"
+        );
+        assert!(matches!(container.entry(id(1)), Ok(Entry::Occupied(_))));
+
+        if let Ok(Entry::Occupied(mut entry)) = container.entry(id(1)) {
+            assert_eq!(entry.reference(), &1);
+            assert_eq!(entry.set_reference(4), 1);
+        }
+        assert_eq!(container.get_ids_from_reference(&4), Some(vec![id(1)]));
+
+        container.remove(id(1)).unwrap();
+        assert!(matches!(container.entry(id(1)), Ok(Entry::Vacant(_))));
+        container
+            .entry(id(1))
+            .unwrap()
+            .and_modify(|value| value.push('?'))
+            .or_insert_with(|| "y".to_string(), 2);
+        assert_eq!(container.get_from_id(id(1)), Some(&"y".to_string()));
+
+        assert_eq!(container.entry(id(3)).err(), Some(Error::IdNotFound(3)));
+        assert_eq!(container.add("z".to_string(), 0).unwrap(), id(3));
+        assert!(matches!(container.entry(id(3)), Ok(Entry::Occupied(_))));
+
+        if let Ok(Entry::Occupied(mut entry)) = container.entry(id(2)) {
+            assert_eq!(entry.id(), id(2));
+            assert_eq!(entry.insert("c2".to_string()), "c");
+            assert_eq!(entry.get(), "c2");
+            assert_eq!(entry.remove(), "c2");
+        }
+        assert_eq!(container.get_from_id(id(2)), None);
+        assert_eq!(container.entry(id(2)).unwrap().id(), id(2));
+    }
+
+    /// Tests the standard trait implementations of the Container struct to
+    /// ensure indexing by handle, collecting, extending, comparing, hashing
+    /// and debug formatting all work on the id to value mappings.
+    #[test]
+    fn test_standard_traits() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let hash = |container: &ReferenceContainer<String>| {
+            let mut hasher = DefaultHasher::new();
+            container.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let mut container: ReferenceContainer<String> = [("a", 0), ("b", 1), ("c", 1)]
+            .into_iter()
+            .map(|(data, reference)| (data.to_string(), reference))
+            .collect();
+        let handle = container.handle_of(id(1)).unwrap();
+        container[handle].push('!');
+        assert_eq!(container[handle], "b!");
+        container[id(2)].push('?');
+        assert_eq!(container[id(2)], "c?");
+        container[id(2)].pop();
+
+        container.extend([("d".to_string(), 2)]);
+        assert_eq!(container.get_from_id(id(3)), Some(&"d".to_string()));
+        assert_eq!(
+            format!("{container:?}"),
+            r#"{0: (0, "a"), 1: (1, "b!"), 2: (1, "c"), 3: (2, "d")}"#
+        );
+
+        let mut other = setup_container();
+        other.update(id(1), "b!".to_string()).unwrap();
+        other.add("d".to_string(), 2).unwrap();
+        other.remove_by_reference(&0).unwrap();
+        other.entry(id(0)).unwrap().or_insert("a".to_string(), 0);
+        assert_ne!(
+            container.ids().collect::<Vec<_>>(),
+            other.ids().collect::<Vec<_>>()
+        );
+        assert_eq!(container, other);
+        assert_eq!(hash(&container), hash(&other));
+
+        other.update(id(2), "x".to_string()).unwrap();
+        assert_ne!(container, other);
+    }
+
+    /// Tests a container with a narrow id type to ensure the ids are stored
+    /// in that type, that running out of ids is reported by 'add', and that
+    /// freed ids can still be recycled once the id type is exhausted.
+    #[test]
+    fn test_id_type() {
+        let mut container: ReferenceContainer<u32, u8> = ReferenceContainer::with_id_type();
+        for value in 0..256 {
+            container.add(value, 0).unwrap();
+        }
+        assert_eq!(container.add(256, 0), Err(Error::CapacityExhausted));
+
+        let last = container.get_id_from_index(255).unwrap();
+        assert_eq!(last.value(), u8::MAX);
+        container.remove(last).unwrap();
+        assert_eq!(container.add(256, 0), Ok(last));
+        assert_eq!(container[last], 256);
+
+        let json = serde_json::to_string(&container).unwrap();
+        let restored: ReferenceContainer<u32, u8> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, container);
+    }
+
+    /// Tests the capacity methods of the Container struct to ensure space can
+    /// be reserved and released, and that 'compact_ids' renumbers the live
+    /// ids densely and reports the remap from old to new ids.
+    #[test]
+    fn test_capacity_and_compaction() {
+        let mut container: ReferenceContainer<String> = ReferenceContainer::with_capacity(8);
+        assert!(container.capacity() >= 8);
+        for value in ["a", "b", "c", "d", "e"] {
+            container.add(value.to_string(), 0).unwrap();
+        }
+        container.reserve(10);
+        assert!(container.capacity() >= 15);
+
+        let handle = container.handle_of(id(4)).unwrap();
+        container.remove(id(0)).unwrap();
+        container.remove(id(2)).unwrap();
+        container.shrink_to_fit();
+        assert!(container.capacity() < 15);
+
+        let remap = container.compact_ids();
+        assert_eq!(
+            remap,
+            vec![None, Some(id(1)), None, Some(id(2)), Some(id(0))]
+        );
+        assert_eq!(
+            container.ids().collect::<Vec<_>>(),
+            vec![id(0), id(1), id(2)]
+        );
+        assert_eq!(container.get_from_id(id(0)), Some(&"e".to_string()));
+        assert_eq!(container.get_from_id(id(1)), Some(&"b".to_string()));
+        assert_eq!(container.get_from_id(id(2)), Some(&"d".to_string()));
+        assert_eq!(container.get_from_id(id(3)), None);
+        assert_eq!(container.get_from_handle(handle), None);
+        assert_eq!(container.add("f".to_string(), 1), Ok(id(3)));
+    }
+
+    /// Tests the bulk methods of the Container struct to ensure 'add_many'
+    /// recycles free ids before appending new ones, that 'remove_many'
+    /// reports every id it could not remove, and that running out of ids
+    /// leaves the container untouched.
+    #[test]
+    fn test_bulk_operations() {
+        let mut container = setup_container();
+        container.remove(id(1)).unwrap();
+        assert_eq!(
+            container.add_many_with_reference(["d", "e", "f"].map(String::from), 2),
+            Ok(vec![id(1), id(3), id(4)])
+        );
+        assert_eq!(container.size(), 5);
+        assert_eq!(container.get_from_id(id(1)), Some(&"d".to_string()));
+        assert_eq!(container.get_from_id(id(4)), Some(&"f".to_string()));
+
+        assert_eq!(
+            container.remove_many(&[id(0), id(9), id(3), id(0)]),
+            Err(vec![Error::IdNotFound(9), Error::IdNotFound(0)])
+        );
+        assert_eq!(container.size(), 3);
+        assert_eq!(container.remove_many(&[id(1)]), Ok(()));
+
+        assert_eq!(
+            container.add_many([("g".to_string(), 3), ("h".to_string(), 4)]),
+            Ok(vec![id(1), id(3)])
+        );
+        assert_eq!(container.get_ids_from_reference(&2), Some(vec![id(4)]));
+        assert_eq!(container.get_ids_from_reference(&4), Some(vec![id(3)]));
+
+        let mut narrow: ReferenceContainer<u32, u8> = ReferenceContainer::with_id_type();
+        assert_eq!(
+            narrow.add_many_with_reference(vec![0; 300], 0),
+            Err(Error::CapacityExhausted)
+        );
+        assert!(narrow.empty());
+        assert_eq!(
+            narrow
+                .add_many_with_reference(vec![0; 256], 0)
+                .map(|ids| ids.len()),
+            Ok(256)
+        );
+    }
+
+    /// Tests the 'retain' and 'drain_filter' methods of the Container struct
+    /// to ensure matching elements are removed in a single pass, that the
+    /// predicate receives the references of the elements, and that the ids
+    /// of the remaining elements stay valid.
+    #[test]
+    fn test_retain_and_drain_filter() {
+        let mut container = setup_container();
+        container
+            .add_many([("d".to_string(), 2), ("e".to_string(), 1)])
+            .unwrap();
+        container.retain(|id, reference, data| {
+            data.push('!');
+            *reference != 1 || id.value() == 4
+        });
+        assert_eq!(container.size(), 3);
+        assert_eq!(container.get_from_id(id(0)), Some(&"a!".to_string()));
+        assert_eq!(container.get_from_id(id(3)), Some(&"d!".to_string()));
+        assert_eq!(container.get_ids_from_reference(&1), Some(vec![id(4)]));
+
+        let mut drained: Vec<_> = container
+            .drain_filter(|_, reference, _| *reference > 0)
+            .collect();
+        drained.sort();
+        assert_eq!(
+            drained,
+            vec![(id(3), "d!".to_string()), (id(4), "e!".to_string())]
+        );
+        assert_eq!(container.ids().collect::<Vec<_>>(), vec![id(0)]);
+        assert_eq!(container.get_ids_from_reference(&0), Some(vec![id(0)]));
+    }
+
+    /// Tests the 'sort_by', 'sort_by_key' and 'sort_unstable_by' methods of
+    /// the Container struct to ensure the values and their references are
+    /// reordered together while every id still resolves to its own value.
+    #[test]
+    fn test_sort_by() {
+        let mut container = setup_unsorted_container();
+        container
+            .add_many([("e".to_string(), 0), ("d".to_string(), 1)])
+            .unwrap();
+        container.remove(id(1)).unwrap();
+
+        container.sort_by(|a, b| b.cmp(a));
+        assert_eq!(
+            container.values().collect::<Vec<_>>(),
+            vec!["e", "d", "c", "a"]
+        );
+        assert_eq!(
+            container.ids().collect::<Vec<_>>(),
+            vec![id(3), id(4), id(2), id(0)]
+        );
+        assert_eq!(
+            container.get_ids_from_reference(&1),
+            Some(vec![id(4), id(2)])
+        );
+
+        container.sort_by_key(|value| value.clone());
+        assert_eq!(
+            container.values().collect::<Vec<_>>(),
+            vec!["a", "c", "d", "e"]
+        );
+        assert_eq!(container.get_from_id(id(4)), Some(&"d".to_string()));
+        assert_eq!(container.get_ids_from_reference(&2), Some(vec![id(0)]));
+
+        container.sort_unstable_by(|a, b| b.cmp(a));
+        assert_eq!(
+            container.values().collect::<Vec<_>>(),
+            vec!["e", "d", "c", "a"]
+        );
+        assert_eq!(container.get_ids_from_reference(&0), Some(vec![id(3)]));
+        assert_eq!(container.add("f".to_string(), 0), Ok(id(1)));
+    }
+
+    /// Tests the sort methods of the Container struct after removals, to
+    /// ensure only the live elements are reordered, the free ids and their
+    /// 'data_index' entries are left untouched, and the order within each
+    /// reference group follows the requested secondary ordering.
+    #[test]
+    fn test_sort_with_free_ids() {
+        let mut container = setup_unsorted_container();
+        container
+            .add_many([
+                ("e".to_string(), 1),
+                ("d".to_string(), 2),
+                ("f".to_string(), 1),
+            ])
+            .unwrap();
+        container.remove(id(1)).unwrap();
+        container.remove(id(4)).unwrap();
+        let free: Vec<_> = container.id[container.size()..].to_vec();
+
+        container.sort();
+        assert_eq!(container.validate(), Ok(()));
+        assert_eq!(container.id[container.size()..], free);
+        assert_eq!(
+            container
+                .iter_with_references()
+                .map(|(_, r, _)| *r)
+                .collect::<Vec<_>>(),
+            vec![1, 1, 1, 2]
+        );
+        assert_eq!(container.add("g".to_string(), 0), Ok(id(4)));
+        assert_eq!(container.add("h".to_string(), 0), Ok(id(1)));
+
+        container.sort_unstable();
+        assert_eq!(container.validate(), Ok(()));
+        assert_eq!(container.get_from_id(id(1)), Some(&"h".to_string()));
+
+        container.sort_by_reference_then_id();
+        assert_eq!(
+            container.ids().collect::<Vec<_>>(),
+            vec![id(1), id(4), id(2), id(3), id(5), id(0)]
+        );
+
+        container.sort_by_reference_then_by(|a, b| b.cmp(a));
+        assert_eq!(
+            container.values().collect::<Vec<_>>(),
+            vec!["h", "g", "f", "e", "c", "a"]
+        );
+        assert_eq!(container.validate(), Ok(()));
+    }
+
+    /// Tests the 'reference_index' map of the Container struct to ensure it
+    /// follows additions, removals, sorting, reference changes through
+    /// entries, compaction and serialization, and that 'count_by_reference'
+    /// and 'references' report the distinct references of the live values.
+    #[test]
+    fn test_reference_index() {
+        let mut container = setup_container();
+        assert_eq!(container.count_by_reference(&1), 2);
+        assert_eq!(container.count_by_reference(&5), 0);
+
+        container
+            .add_many([("d".to_string(), 2), ("e".to_string(), 1)])
+            .unwrap();
+        container.remove(id(1)).unwrap();
+        container.sort_by(|a, b| b.cmp(a));
+        assert_eq!(container.count_by_reference(&1), 2);
+        assert_eq!(
+            container.get_ids_from_reference(&1),
+            Some(vec![id(4), id(2)])
+        );
+
+        if let Ok(Entry::Occupied(mut entry)) = container.entry(id(2)) {
+            entry.set_reference(3);
+        }
+        let mut references: Vec<_> = container.references().copied().collect();
+        references.sort();
+        assert_eq!(references, vec![0, 1, 2, 3]);
+        assert_eq!(container.get_ids_from_reference(&3), Some(vec![id(2)]));
+
+        assert_eq!(container.remove_by_reference(&1), Ok(()));
+        assert_eq!(container.count_by_reference(&1), 0);
+        assert_eq!(container.get_ids_from_reference(&1), None);
+
+        let remap = container.compact_ids();
+        let new_id = remap[2].unwrap();
+        assert_eq!(container.get_ids_from_reference(&3), Some(vec![new_id]));
+
+        let json = serde_json::to_string(&container).unwrap();
+        let restored: ReferenceContainer<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.reference_index, container.reference_index);
+
+        container.clear();
+        assert_eq!(container.references().count(), 0);
+    }
+
+    /// Tests the iterator and slice based reference queries of the Container
+    /// struct to ensure they visit every value of a reference, wherever it is
+    /// stored, are empty for unknown references, and only hand out slices
+    /// while the values of a reference are contiguous.
+    #[test]
+    fn test_reference_queries() {
+        let mut container = setup_container();
+        container
+            .add_many([("d".to_string(), 0), ("e".to_string(), 1)])
+            .unwrap();
+
+        let mut ids: Vec<_> = container.ids_by_reference(&1).collect();
+        ids.sort();
+        assert_eq!(ids, vec![id(1), id(2), id(4)]);
+        let mut values: Vec<_> = container.values_by_reference(&0).collect();
+        values.sort();
+        assert_eq!(values, vec!["a", "d"]);
+        assert_eq!(container.ids_by_reference(&7).count(), 0);
+        assert_eq!(container.values_by_reference(&7).count(), 0);
+
+        for value in container.values_by_reference_mut(&0) {
+            value.push('!');
+        }
+        assert_eq!(container.get_from_id(id(0)), Some(&"a!".to_string()));
+        assert_eq!(container.get_from_id(id(3)), Some(&"d!".to_string()));
+        assert_eq!(container.get_from_id(id(1)), Some(&"b".to_string()));
+
+        assert_eq!(container.slice_by_reference(&0), None);
+        assert_eq!(container.slice_by_reference(&7), None);
+        assert_eq!(container.slice_by_reference(&1), None);
+
+        container.sort();
+        assert_eq!(
+            container.slice_by_reference(&0),
+            Some(&["a!".to_string(), "d!".to_string()][..])
+        );
+        let slice = container.slice_by_reference_mut(&1).unwrap();
+        assert_eq!(slice, ["b", "c", "e"]);
+        slice.reverse();
+        assert_eq!(container.get_from_id(id(1)), Some(&"e".to_string()));
+        assert_eq!(container.get_from_id(id(4)), Some(&"b".to_string()));
+        let values: Vec<_> = container.values_by_reference_mut(&1).collect();
+        assert_eq!(values, ["e", "c", "b"]);
+    }
+
+    /// Tests the 'set_reference' and 'move_reference' methods of the
+    /// Container struct to ensure ids stay the same, the reference index
+    /// follows the changes, a sorted container stays sorted, and a container
+    /// that was never sorted is not reordered.
+    #[test]
+    fn test_set_and_move_reference() {
+        let mut container = setup_unsorted_container();
+        assert_eq!(container.set_reference(id(0), 1), Ok(2));
+        assert_eq!(container.set_reference(id(5), 1), Err(Error::IdNotFound(5)));
+        assert_eq!(
+            container.ids().collect::<Vec<_>>(),
+            vec![id(0), id(1), id(2)]
+        );
+        assert_eq!(
+            container.get_ids_from_reference(&1),
+            Some(vec![id(0), id(2)])
+        );
+        assert_eq!(container.get_ids_from_reference(&2), None);
+
+        let mut ordered = setup_container();
+        assert_eq!(ordered.set_reference(id(0), 2), Ok(0));
+        assert_eq!(ordered.ids().collect::<Vec<_>>(), vec![id(0), id(1), id(2)]);
+
+        container
+            .add_many([("d".to_string(), 3), ("e".to_string(), 2)])
+            .unwrap();
+        container.sort();
+        assert_eq!(container.set_reference(id(1), 2), Ok(0));
+        assert_eq!(
+            container
+                .iter_with_references()
+                .map(|(id, r, _)| (id.value(), *r))
+                .collect::<Vec<_>>(),
+            vec![(0, 1), (2, 1), (4, 2), (1, 2), (3, 3)]
+        );
+        assert_eq!(container.set_reference(id(3), 0), Ok(3));
+        assert_eq!(
+            container
+                .iter_with_references()
+                .map(|(id, r, _)| (id.value(), *r))
+                .collect::<Vec<_>>(),
+            vec![(3, 0), (0, 1), (2, 1), (4, 2), (1, 2)]
+        );
+        assert_eq!(container.validate(), Ok(()));
+
+        assert_eq!(container.move_reference(&2, 0), Ok(()));
+        assert_eq!(
+            container.move_reference(&2, 0),
+            Err(Error::ReferenceNotFound(2))
+        );
+        assert_eq!(
+            container
+                .iter_with_references()
+                .map(|(id, r, _)| (id.value(), *r))
+                .collect::<Vec<_>>(),
+            vec![(3, 0), (4, 0), (1, 0), (0, 1), (2, 1)]
+        );
+        assert_eq!(container.count_by_reference(&0), 3);
+        assert_eq!(
+            container.slice_by_reference(&0).map(<[String]>::len),
+            Some(3)
+        );
+        assert_eq!(container.get_from_id(id(1)), Some(&"b".to_string()));
+        assert_eq!(container.validate(), Ok(()));
+
+        assert_eq!(container.move_reference(&0, 1), Ok(()));
+        assert_eq!(
+            container
+                .iter_with_references()
+                .map(|(id, r, _)| (id.value(), *r))
+                .collect::<Vec<_>>(),
+            vec![(0, 1), (2, 1), (3, 1), (4, 1), (1, 1)]
+        );
+        container.remove(id(0)).unwrap();
+        assert_eq!(container.set_reference(id(3), 0), Ok(1));
+        assert_eq!(
+            container.ids().collect::<Vec<_>>(),
+            vec![id(1), id(2), id(3), id(4)]
+        );
+        assert_eq!(container.validate(), Ok(()));
+    }
+
+    /// Tests the Container struct with non-integer references to ensure
+    /// string references can be looked up by `&str`, that sorting orders the
+    /// values by reference, and that composite keys and serialization work.
+    #[test]
+    fn test_generic_references() {
+        let mut container: ReferenceContainer<u32, usize, String> = ReferenceContainer::new();
+        container.add(1, "scene-b".to_string()).unwrap();
+        container.add(2, "scene-a".to_string()).unwrap();
+        container.add(3, "scene-b".to_string()).unwrap();
 
-        assert_eq!(container.get_ids_from_reference(0), Some(vec![1]));
-        assert_eq!(container.get_ids_from_reference(1), Some(vec![2]));
-        assert_eq!(container.get_ids_from_reference(2), Some(vec![0]));
+        assert_eq!(container.count_by_reference("scene-b"), 2);
+        assert_eq!(
+            container.get_ids_from_reference("scene-b"),
+            Some(vec![Id::new(0), Id::new(2)])
+        );
+        container.sort();
+        assert_eq!(container.values().collect::<Vec<_>>(), vec![&2, &1, &3]);
+        assert_eq!(container.slice_by_reference("scene-b"), Some(&[1, 3][..]));
+
+        assert_eq!(
+            container.move_reference("scene-a", "scene-c".to_string()),
+            Ok(())
+        );
+        assert_eq!(container.values().collect::<Vec<_>>(), vec![&1, &3, &2]);
+        assert_eq!(
+            container.remove_by_reference("scene-a"),
+            Err(Error::ReferenceNotFound("scene-a".to_string()))
+        );
+
+        let json = serde_json::to_string(&container).unwrap();
+        let restored: ReferenceContainer<u32, usize, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, container);
+        assert_eq!(restored.count_by_reference("scene-c"), 1);
+
+        let mut composite: ReferenceContainer<&str, u8, (u8, char)> =
+            ReferenceContainer::with_id_type();
+        composite.add("x", (1, 'b')).unwrap();
+        composite.add("y", (1, 'a')).unwrap();
+        composite.sort();
+        assert_eq!(composite.values().collect::<Vec<_>>(), vec![&"y", &"x"]);
+        assert_eq!(
+            composite.take_by_reference(&(1, 'b')),
+            vec![(Id::new(0), "x")]
+        );
+    }
+
+    /// Tests the range queries of the Container struct to ensure values can
+    /// be found and removed by a range of references, that the smallest and
+    /// largest references are reported, and that 'pop_min_reference' removes
+    /// the values of the smallest reference group by group.
+    #[test]
+    fn test_reference_ranges() {
+        let mut container = setup_container();
+        container
+            .add_many([
+                ("d".to_string(), 5),
+                ("e".to_string(), 3),
+                ("f".to_string(), 3),
+            ])
+            .unwrap();
+        assert_eq!(container.min_reference(), Some(&0));
+        assert_eq!(container.max_reference(), Some(&5));
+        assert_eq!(
+            container.references().copied().collect::<Vec<_>>(),
+            vec![0, 1, 3, 5]
+        );
+
+        let mut ids: Vec<_> = container.ids_in_reference_range(1..5).collect();
+        let references: Vec<_> = ids
+            .iter()
+            .filter_map(|&id| container.reference_of(id))
+            .collect();
+        assert_eq!(references, vec![&1, &1, &3, &3]);
+        ids.sort();
+        assert_eq!(ids, vec![id(1), id(2), id(4), id(5)]);
+        assert_eq!(container.ids_in_reference_range(6..).count(), 0);
+        assert_eq!(
+            container.ids_in_reference_range(..=0).collect::<Vec<_>>(),
+            vec![id(0)]
+        );
+
+        assert_eq!(container.remove_reference_range(2..=3), 2);
+        assert_eq!(container.remove_reference_range(2..=3), 0);
+        assert_eq!(container.size(), 4);
+        assert_eq!(container.count_by_reference(&3), 0);
+
+        assert_eq!(
+            container.pop_min_reference(),
+            Some((id(0), 0, "a".to_string()))
+        );
+        assert_eq!(
+            container.pop_min_reference(),
+            Some((id(1), 1, "b".to_string()))
+        );
+        assert_eq!(
+            container.pop_min_reference(),
+            Some((id(2), 1, "c".to_string()))
+        );
+        assert_eq!(container.min_reference(), Some(&5));
+        assert_eq!(
+            container.pop_min_reference().map(|(id, _, _)| id),
+            Some(id(3))
+        );
+        assert_eq!(container.pop_min_reference(), None);
+        assert_eq!(container.max_reference(), None);
     }
 }
@@ -0,0 +1,48 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::id::{Id, IdInt};
+
+/// Describes how the dense layout of a container changed when an element
+/// was removed. Removing an element swaps the last element of the 'data'
+/// vector into the hole it leaves behind, so at most one other element
+/// changes its index.
+pub struct Removal<T, I = usize> {
+    /// The index the removed element occupied in the 'data' vector.
+    pub removed_index: usize,
+    /// The element that was moved to fill the hole, as `(id, from, to)`,
+    /// or `None` if the removed element was the last one and nothing moved.
+    pub moved: Option<(Id<T, I>, usize, usize)>,
+}
+
+impl<T, I: IdInt> Clone for Removal<T, I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, I: IdInt> Copy for Removal<T, I> {}
+
+impl<T, I: IdInt> PartialEq for Removal<T, I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.removed_index == other.removed_index && self.moved == other.moved
+    }
+}
+
+impl<T, I: IdInt> Eq for Removal<T, I> {}
+
+impl<T, I: IdInt> Hash for Removal<T, I> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.removed_index.hash(state);
+        self.moved.hash(state);
+    }
+}
+
+impl<T, I: IdInt> fmt::Debug for Removal<T, I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Removal")
+            .field("removed_index", &self.removed_index)
+            .field("moved", &self.moved)
+            .finish()
+    }
+}